@@ -0,0 +1,364 @@
+use crate::Version;
+use breezyshim::tree::{MutableTree, Tree};
+use breezyshim::workingtree::WorkingTree;
+use chrono::NaiveDate;
+use std::path::{Path, PathBuf};
+
+/// Globs for AppStream metainfo files, relative to the root of the tree.
+pub const METAINFO_GLOBS: &[&str] = &["**/*.metainfo.xml", "**/*.appdata.xml"];
+
+/// Find the versioned AppStream metainfo files in a tree.
+///
+/// Unversioned files, such as copies in build directories, are skipped.
+pub fn find_metainfo_files(tree: &WorkingTree) -> Vec<PathBuf> {
+    let mut ret = vec![];
+    for pattern in METAINFO_GLOBS {
+        for path in crate::iter_glob(tree, pattern) {
+            if tree.is_versioned(&path) && !ret.contains(&path) {
+                ret.push(path);
+            }
+        }
+    }
+    ret
+}
+
+#[derive(Debug)]
+pub enum Error {
+    BrzError(Box<breezyshim::error::Error>),
+    InvalidData(String),
+}
+
+impl From<breezyshim::error::Error> for Error {
+    fn from(e: breezyshim::error::Error) -> Self {
+        Error::BrzError(Box::new(e))
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self {
+            Error::BrzError(e) => write!(f, "Tree error: {}", e),
+            Error::InvalidData(e) => write!(f, "Invalid metainfo: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Convert the changes for a release from a NEWS file into AppStream
+/// description markup.
+///
+/// Bulleted entries (starting with `*` or `-`) become list items, anything
+/// else becomes a paragraph.
+pub fn news_to_description(changes: &str) -> Vec<String> {
+    enum Block {
+        Paragraph(String),
+        List(Vec<String>),
+    }
+
+    let mut blocks: Vec<Block> = vec![];
+    let mut in_block = false;
+    for line in changes.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            in_block = false;
+            continue;
+        }
+        if let Some(item) = line.strip_prefix("* ").or_else(|| line.strip_prefix("- ")) {
+            match blocks.last_mut() {
+                Some(Block::List(items)) => items.push(item.trim().to_string()),
+                _ => blocks.push(Block::List(vec![item.trim().to_string()])),
+            }
+        } else if !in_block {
+            blocks.push(Block::Paragraph(line.to_string()));
+        } else {
+            match blocks.last_mut() {
+                Some(Block::List(items)) => {
+                    let item = items.last_mut().unwrap();
+                    item.push(' ');
+                    item.push_str(line);
+                }
+                Some(Block::Paragraph(p)) => {
+                    p.push(' ');
+                    p.push_str(line);
+                }
+                None => unreachable!(),
+            }
+        }
+        in_block = true;
+    }
+
+    let mut ret = vec![];
+    for block in blocks {
+        match block {
+            Block::Paragraph(p) => ret.push(format!("<p>{}</p>", escape(&p))),
+            Block::List(items) => {
+                ret.push("<ul>".to_string());
+                for item in items {
+                    ret.push(format!("  <li>{}</li>", escape(&item)));
+                }
+                ret.push("</ul>".to_string());
+            }
+        }
+    }
+    ret
+}
+
+/// Render a `<release>` element, indented by `indent` with `unit` per level.
+fn render_release(
+    new_version: &Version,
+    release_date: NaiveDate,
+    release_notes: Option<&str>,
+    indent: &str,
+    unit: &str,
+) -> String {
+    let tag = format!(
+        "<release version=\"{}\" date=\"{}\"",
+        escape(&new_version.to_string()),
+        release_date.format("%Y-%m-%d")
+    );
+    let description = release_notes.map(news_to_description).unwrap_or_default();
+    if description.is_empty() {
+        return format!("{}{}/>", indent, tag);
+    }
+    let mut lines = vec![
+        format!("{}{}>", indent, tag),
+        format!("{}{}<description>", indent, unit),
+    ];
+    for line in description {
+        lines.push(format!("{}{}{}{}", indent, unit, unit, line));
+    }
+    lines.push(format!("{}{}</description>", indent, unit));
+    lines.push(format!("{}</release>", indent));
+    lines.join("\n")
+}
+
+/// Return the indentation of the line that contains byte offset `pos`.
+fn indentation_at(text: &str, pos: usize) -> &str {
+    let start = text[..pos].rfind('\n').map_or(0, |i| i + 1);
+    let line = &text[start..];
+    &line[..line.len() - line.trim_start_matches([' ', '\t']).len()]
+}
+
+/// Add a `<release>` entry for a new version to an AppStream metainfo file.
+///
+/// The new release is added as the first entry in `<releases>`, which is
+/// created if it does not exist yet. An existing entry for the same version is
+/// replaced.
+pub fn update_metainfo(
+    text: &str,
+    new_version: &Version,
+    release_date: NaiveDate,
+    release_notes: Option<&str>,
+) -> Result<String, Error> {
+    if !text.contains("<component") || !text.contains("</component>") {
+        return Err(Error::InvalidData("missing <component>".to_string()));
+    }
+
+    // Drop any existing entry for this version, e.g. from an earlier attempt.
+    let version_attr = regex::escape(&format!("version=\"{}\"", new_version.to_string()));
+    let existing = regex::Regex::new(&format!(
+        r#"(?s)\n[ \t]*<release\s[^>]*{}[^>]*?(?:/>|>.*?</release>)"#,
+        version_attr
+    ))
+    .unwrap();
+    let text = existing.replace(text, "");
+
+    let (indent, unit) = if let Some(m) = lazy_regex::regex_find!(r"<releases\s*/?>", &text) {
+        let indent = indentation_at(&text, text.find(m).unwrap()).to_string();
+        let unit = if indent.is_empty() {
+            "  ".to_string()
+        } else {
+            indent.clone()
+        };
+        (indent, unit)
+    } else {
+        let component_end = text.rfind("</component>").unwrap();
+        let indent = indentation_at(&text, component_end).to_string();
+        let unit = "  ".to_string();
+        (format!("{}{}", indent, unit), unit)
+    };
+
+    let release = render_release(
+        new_version,
+        release_date,
+        release_notes,
+        &format!("{}{}", indent, unit),
+        &unit,
+    );
+
+    let updated = if let Some(m) = lazy_regex::regex_find!(r"<releases\s*>", &text) {
+        let pos = text.find(m).unwrap() + m.len();
+        format!("{}\n{}{}", &text[..pos], release, &text[pos..])
+    } else if let Some(m) = lazy_regex::regex_find!(r"<releases\s*/>", &text) {
+        text.replacen(
+            m,
+            &format!("<releases>\n{}\n{}</releases>", release, indent),
+            1,
+        )
+    } else {
+        let component_end = text.rfind("</component>").unwrap();
+        let line_start = text[..component_end].rfind('\n').map_or(0, |i| i + 1);
+        format!(
+            "{}{}<releases>\n{}\n{}</releases>\n{}",
+            &text[..line_start],
+            indent,
+            release,
+            indent,
+            &text[line_start..]
+        )
+    };
+
+    Ok(updated)
+}
+
+/// Add a release entry to an AppStream metainfo file in a tree.
+pub fn update_metainfo_in_tree(
+    tree: &dyn MutableTree,
+    path: &Path,
+    new_version: &Version,
+    release_date: NaiveDate,
+    release_notes: Option<&str>,
+) -> Result<(), Error> {
+    let text = tree.get_file_text(path)?;
+    let text = String::from_utf8(text).map_err(|e| Error::InvalidData(e.to_string()))?;
+    let updated = update_metainfo(&text, new_version, release_date, release_notes)?;
+    tree.put_file_bytes_non_atomic(path, updated.as_bytes())?;
+    Ok(())
+}
+
+/// Validate that an AppStream metainfo file can be updated.
+pub fn validate_metainfo(tree: &dyn breezyshim::tree::Tree, path: &Path) -> Result<(), Error> {
+    let text = tree.get_file_text(path)?;
+    let text = String::from_utf8(text).map_err(|e| Error::InvalidData(e.to_string()))?;
+    update_metainfo(
+        &text,
+        &Version {
+            major: 0,
+            minor: Some(0),
+            micro: Some(0),
+        },
+        chrono::Utc::now().date_naive(),
+        None,
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_news_to_description() {
+        assert_eq!(
+            news_to_description(
+                " * Fix a bug in the frobnicator.\n   (Jelmer Vernooĳ, #12)\n\n * Add <blah> support.\n\n Some other text.\n"
+            ),
+            vec![
+                "<ul>",
+                "  <li>Fix a bug in the frobnicator. (Jelmer Vernooĳ, #12)</li>",
+                "  <li>Add &lt;blah&gt; support.</li>",
+                "</ul>",
+                "<p>Some other text.</p>",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_update_metainfo_existing_releases() {
+        let text = r#"<?xml version="1.0" encoding="UTF-8"?>
+<component type="desktop-application">
+  <id>org.example.App</id>
+  <releases>
+    <release version="0.1.0" date="2019-12-31"/>
+  </releases>
+</component>
+"#;
+        assert_eq!(
+            update_metainfo(
+                text,
+                &"0.2.0".parse().unwrap(),
+                NaiveDate::from_ymd_opt(2024, 1, 5).unwrap(),
+                Some(" * Fix a bug.\n"),
+            )
+            .unwrap(),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<component type="desktop-application">
+  <id>org.example.App</id>
+  <releases>
+    <release version="0.2.0" date="2024-01-05">
+      <description>
+        <ul>
+          <li>Fix a bug.</li>
+        </ul>
+      </description>
+    </release>
+    <release version="0.1.0" date="2019-12-31"/>
+  </releases>
+</component>
+"#
+        );
+    }
+
+    #[test]
+    fn test_update_metainfo_replaces_same_version() {
+        let text = r#"<component>
+  <releases>
+    <release version="0.2.0" date="2024-01-01">
+      <description><p>Old</p></description>
+    </release>
+    <release version="0.1.0" date="2019-12-31"/>
+  </releases>
+</component>
+"#;
+        assert_eq!(
+            update_metainfo(
+                text,
+                &"0.2.0".parse().unwrap(),
+                NaiveDate::from_ymd_opt(2024, 1, 5).unwrap(),
+                None,
+            )
+            .unwrap(),
+            r#"<component>
+  <releases>
+    <release version="0.2.0" date="2024-01-05"/>
+    <release version="0.1.0" date="2019-12-31"/>
+  </releases>
+</component>
+"#
+        );
+    }
+
+    #[test]
+    fn test_update_metainfo_no_releases() {
+        let text = "<component>\n  <id>org.example.App</id>\n</component>\n";
+        assert_eq!(
+            update_metainfo(
+                text,
+                &"0.2.0".parse().unwrap(),
+                NaiveDate::from_ymd_opt(2024, 1, 5).unwrap(),
+                None,
+            )
+            .unwrap(),
+            "<component>\n  <id>org.example.App</id>\n  <releases>\n    <release version=\"0.2.0\" date=\"2024-01-05\"/>\n  </releases>\n</component>\n"
+        );
+    }
+
+    #[test]
+    fn test_update_metainfo_invalid() {
+        assert!(update_metainfo(
+            "<foo/>",
+            &"0.2.0".parse().unwrap(),
+            NaiveDate::from_ymd_opt(2024, 1, 5).unwrap(),
+            None,
+        )
+        .is_err());
+    }
+}
@@ -1,11 +1,12 @@
 use breezyshim::tree::{MutableTree, Tree, WorkingTree};
 
-use std::path::Path;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
 #[derive(Debug)]
 pub enum Error {
-    BrzError(breezyshim::error::Error),
+    BrzError(Box<breezyshim::error::Error>),
     CratesIoError(crates_io_api::Error),
     VersionError(String),
     Other(String),
@@ -13,7 +14,7 @@ pub enum Error {
 
 impl From<breezyshim::error::Error> for Error {
     fn from(e: breezyshim::error::Error) -> Self {
-        Error::BrzError(e)
+        Error::BrzError(Box::new(e))
     }
 }
 
@@ -36,34 +37,337 @@ impl std::fmt::Display for Error {
 
 impl std::error::Error for Error {}
 
-pub fn get_owned_crates(user: &str) -> Result<Vec<url::Url>, Error> {
+/// A crate found by discovery.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnedCrate {
+    pub name: String,
+
+    /// Repository the crate is developed in.
+    pub repository: url::Url,
+
+    /// Directory of the crate in the repository, if not at the top.
+    pub subpath: Option<PathBuf>,
+}
+
+/// Normalise the `repository` field of a crate into the URL of the
+/// repository and the directory of the crate in it.
+///
+/// Handles `git+` prefixes, `.git` suffixes, SSH-style `user@host:path`
+/// locations and links into a directory of a branch in a forge UI, such as
+/// `https://github.com/owner/repo/tree/main/subdir`.
+pub fn normalize_repository_url(repository: &str) -> Option<(url::Url, Option<PathBuf>)> {
+    let repository = repository.trim();
+    let repository = repository.strip_prefix("git+").unwrap_or(repository);
+    let mut url = match url::Url::parse(repository) {
+        Ok(url) => url,
+        Err(_) => {
+            let (host, path) = lazy_regex::regex_captures!(r"^[\w.-]+@([^:/]+):(.+)$", repository)
+                .map(|(_, host, path)| (host, path))?;
+            url::Url::parse(&format!("https://{}/{}", host, path)).ok()?
+        }
+    };
+    if !["http", "https", "git", "ssh"].contains(&url.scheme()) || url.host().is_none() {
+        return None;
+    }
+    url.set_query(None);
+    url.set_fragment(None);
+
+    let segments = url
+        .path_segments()
+        .map(|s| s.filter(|s| !s.is_empty()).collect::<Vec<_>>())
+        .unwrap_or_default();
+    let (mut repo, subpath) = match segments
+        .iter()
+        .skip(2)
+        .position(|s| *s == "tree" || *s == "blob")
+        .map(|i| i + 2)
+    {
+        Some(i) if segments.len() > i + 1 => {
+            // GitLab puts a "-" before "tree".
+            let repo_end = if segments[i - 1] == "-" { i - 1 } else { i };
+            let subpath = segments[i + 2..].iter().collect::<PathBuf>();
+            (
+                segments[..repo_end].to_vec(),
+                Some(subpath).filter(|p| !p.as_os_str().is_empty()),
+            )
+        }
+        _ => (segments, None),
+    };
+    if let Some(last) = repo.last_mut() {
+        *last = last.strip_suffix(".git").unwrap_or(last);
+    }
+    url.set_path(&repo.join("/"));
+    Some((url, subpath))
+}
+
+/// The number of crates to request per page.
+const CRATES_PAGE_SIZE: u64 = 100;
+
+fn owned_crate(name: &str, repository: Option<&str>) -> Option<OwnedCrate> {
+    let Some(repository) = repository else {
+        log::debug!("Crate {} has no repository", name);
+        return None;
+    };
+    match normalize_repository_url(repository) {
+        Some((repository, subpath)) => Some(OwnedCrate {
+            name: name.to_string(),
+            repository,
+            subpath,
+        }),
+        None => {
+            log::warn!(
+                "Ignoring crate {} with unsupported repository {}",
+                name,
+                repository
+            );
+            None
+        }
+    }
+}
+
+/// Find the crates owned by a user, and the repositories they live in.
+///
+/// `api_url` can be used to point at a registry other than crates.io that
+/// implements the same web API.
+pub async fn get_owned_crates(
+    api_url: Option<&url::Url>,
+    user: &str,
+) -> Result<Vec<OwnedCrate>, Error> {
+    let mut ret = vec![];
+    if let Some(api_url) = api_url.filter(|u| u.as_str() != DEFAULT_API_URL) {
+        let user_id = get_json(api_url, &format!("users/{}", user))
+            .await?
+            .pointer("/user/id")
+            .and_then(|id| id.as_u64())
+            .ok_or_else(|| Error::Other(format!("Unable to find user {} in {}", user, api_url)))?;
+        for page in 1.. {
+            let response = get_json(
+                api_url,
+                &format!(
+                    "crates?user_id={}&page={}&per_page={}",
+                    user_id, page, CRATES_PAGE_SIZE
+                ),
+            )
+            .await?;
+            let crates = response
+                .get("crates")
+                .and_then(|c| c.as_array())
+                .cloned()
+                .unwrap_or_default();
+            let seen = (page - 1) * CRATES_PAGE_SIZE + crates.len() as u64;
+            ret.extend(crates.iter().filter_map(|c| {
+                owned_crate(
+                    c.get("name").and_then(|n| n.as_str())?,
+                    c.get("repository").and_then(|r| r.as_str()),
+                )
+            }));
+            let total = response
+                .pointer("/meta/total")
+                .and_then(|t| t.as_u64())
+                .unwrap_or(0);
+            if crates.is_empty() || seen >= total {
+                break;
+            }
+        }
+        return Ok(ret);
+    }
+
     let client =
-        crates_io_api::SyncClient::new(crate::USER_AGENT, std::time::Duration::from_millis(1000))
+        crates_io_api::AsyncClient::new(crate::USER_AGENT, std::time::Duration::from_millis(1000))
             .map_err(|e| Error::Other(format!("Unable to create crates.io client: {}", e)))?;
 
-    let user = client.user(user)?;
+    let user = client.user(user).await?;
 
-    let query = crates_io_api::CratesQueryBuilder::new().user_id(user.id);
+    for page in 1.. {
+        let query = crates_io_api::CratesQueryBuilder::new()
+            .user_id(user.id)
+            .page(page)
+            .page_size(CRATES_PAGE_SIZE);
 
-    let owned_crates = client.crates(query.build())?;
+        let owned_crates = client.crates(query.build()).await?;
+        let seen = (page - 1) * CRATES_PAGE_SIZE + owned_crates.crates.len() as u64;
+        ret.extend(
+            owned_crates
+                .crates
+                .iter()
+                .filter_map(|c| owned_crate(&c.name, c.repository.as_deref())),
+        );
+        if owned_crates.crates.is_empty() || seen >= owned_crates.meta.total {
+            break;
+        }
+    }
+    Ok(ret)
+}
 
-    Ok(owned_crates
-        .crates
-        .into_iter()
-        .filter_map(|c| c.repository)
-        .map(|r| url::Url::parse(r.as_str()).unwrap())
-        .collect::<Vec<url::Url>>())
+/// The API endpoint of crates.io.
+pub const DEFAULT_API_URL: &str = "https://crates.io/api/v1/";
+
+/// Check whether a version of a crate has already been published.
+///
+/// `api_url` can be used to point at a registry other than crates.io that
+/// implements the same web API.
+pub async fn is_version_published(
+    api_url: Option<&url::Url>,
+    name: &str,
+    version: &str,
+) -> Result<bool, Error> {
+    let api_url = match api_url {
+        Some(api_url) if api_url.as_str() != DEFAULT_API_URL => api_url,
+        _ => {
+            let client = crates_io_api::AsyncClient::new(
+                crate::USER_AGENT,
+                std::time::Duration::from_millis(1000),
+            )
+            .map_err(|e| Error::Other(format!("Unable to create crates.io client: {}", e)))?;
+            return match client.get_crate(name).await {
+                Ok(c) => Ok(c.versions.iter().any(|v| v.num == version)),
+                Err(crates_io_api::Error::NotFound(_)) => Ok(false),
+                Err(e) => Err(e.into()),
+            };
+        }
+    };
+
+    let response = api_get(api_url, &format!("crates/{}/{}", name, version)).await?;
+    let url = response.url().clone();
+    match response.status() {
+        s if s.is_success() => Ok(true),
+        reqwest::StatusCode::NOT_FOUND => Ok(false),
+        s => Err(Error::Other(format!(
+            "Unexpected status {} from {}",
+            s, url
+        ))),
+    }
+}
+
+async fn api_get(api_url: &url::Url, path: &str) -> Result<reqwest::Response, Error> {
+    let url = api_url
+        .join(path)
+        .map_err(|e| Error::Other(format!("Invalid registry URL {}: {}", api_url, e)))?;
+    let client = reqwest::ClientBuilder::new()
+        .user_agent(crate::USER_AGENT)
+        .build()
+        .map_err(|e| Error::Other(format!("Unable to create HTTP client: {}", e)))?;
+    client
+        .get(url.clone())
+        .send()
+        .await
+        .map_err(|e| Error::Other(format!("Unable to fetch {}: {}", url, e)))
+}
+
+async fn get_json(api_url: &url::Url, path: &str) -> Result<serde_json::Value, Error> {
+    let response = api_get(api_url, path).await?;
+    let url = response.url().clone();
+    if !response.status().is_success() {
+        return Err(Error::Other(format!(
+            "Unexpected status {} from {}",
+            response.status(),
+            url
+        )));
+    }
+    let text = response
+        .text()
+        .await
+        .map_err(|e| Error::Other(format!("Unable to read {}: {}", url, e)))?;
+    serde_json::from_str(&text)
+        .map_err(|e| Error::Other(format!("Invalid response from {}: {}", url, e)))
+}
+
+/// The name cargo uses for crates.io, e.g. in `package.publish`.
+pub const CRATES_IO: &str = "crates-io";
+
+/// Find the index URL of a named registry in the text of a cargo
+/// configuration file.
+pub fn find_registry_index_in_config(config_contents: &str, registry: &str) -> Option<String> {
+    let parsed_toml: toml_edit::DocumentMut = config_contents.parse().ok()?;
+    parsed_toml
+        .get("registries")
+        .and_then(|r| r.get(registry))
+        .and_then(|r| r.get("index"))
+        .and_then(|i| i.as_str())
+        .map(|i| i.to_string())
+}
+
+/// Find the index URL of a registry configured in `[registries]`.
+///
+/// Like cargo, this looks at the `CARGO_REGISTRIES_<NAME>_INDEX`
+/// environment variable, the cargo configuration in the tree and the
+/// configuration in `CARGO_HOME`.
+pub fn find_registry_index(tree: &dyn Tree, registry: &str) -> Result<Option<String>, Error> {
+    let var = format!(
+        "CARGO_REGISTRIES_{}_INDEX",
+        registry.to_uppercase().replace('-', "_")
+    );
+    if let Ok(index) = std::env::var(var) {
+        return Ok(Some(index));
+    }
+    for path in [".cargo/config.toml", ".cargo/config"] {
+        if tree.has_filename(Path::new(path)) {
+            let text = tree.get_file_text(Path::new(path))?;
+            if let Some(index) =
+                find_registry_index_in_config(&String::from_utf8_lossy(&text), registry)
+            {
+                return Ok(Some(index));
+            }
+        }
+    }
+    let cargo_home = std::env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".cargo")));
+    if let Some(cargo_home) = cargo_home {
+        for name in ["config.toml", "config"] {
+            if let Ok(text) = std::fs::read_to_string(cargo_home.join(name)) {
+                if let Some(index) = find_registry_index_in_config(&text, registry) {
+                    return Ok(Some(index));
+                }
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// Find the web API of a registry from its index.
+///
+/// Only sparse indexes are supported; for registries with a git index the
+/// API URL has to be configured.
+pub async fn registry_api_url(index: &str) -> Result<url::Url, Error> {
+    let index = index.strip_prefix("sparse+").ok_or_else(|| {
+        Error::Other(format!(
+            "Unable to determine the API of git registry index {}, please configure its api-url",
+            index
+        ))
+    })?;
+    let index = url::Url::parse(&format!("{}/", index.trim_end_matches('/')))
+        .map_err(|e| Error::Other(format!("Invalid registry index {}: {}", index, e)))?;
+    let api = get_json(&index, "config.json")
+        .await?
+        .get("api")
+        .and_then(|a| a.as_str())
+        .map(|a| a.to_string())
+        .ok_or_else(|| Error::Other(format!("Registry {} does not have an API", index)))?;
+    url::Url::parse(&format!("{}/api/v1/", api.trim_end_matches('/')))
+        .map_err(|e| Error::Other(format!("Invalid registry API URL {}: {}", api, e)))
 }
 
 // Define a function to publish a Rust package using Cargo
-pub fn publish(tree: &WorkingTree, subpath: &Path) -> Result<(), Error> {
-    Command::new("cargo")
-        .arg("publish")
+pub fn publish(tree: &WorkingTree, subpath: &Path, registry: Option<&str>) -> Result<(), Error> {
+    let mut command = Command::new("cargo");
+    command.arg("publish");
+    if let Some(registry) = registry {
+        command.arg("--registry").arg(registry);
+    }
+    let status = command
         .current_dir(tree.abspath(subpath)?)
         .spawn()
         .map_err(|e| Error::Other(format!("Unable to spawn cargo publish: {}", e)))?
         .wait()
         .map_err(|e| Error::Other(format!("Unable to wait for cargo publish: {}", e)))?;
+    if !status.success() {
+        return Err(Error::Other(format!(
+            "cargo publish in {} failed: {}",
+            subpath.display(),
+            status
+        )));
+    }
     Ok(())
 }
 
@@ -120,8 +424,18 @@ pub fn update_version(tree: &WorkingTree, new_version: &str) -> Result<(), Error
             .parse()
             .map_err(|e| Error::Other(format!("Unable to parse Cargo.toml: {}", e)))?;
 
-    // Update the version field
-    update_version_in_toml(&mut parsed_toml, new_version)?;
+    let members = workspace_members(tree)?;
+    let member_names = members
+        .iter()
+        .map(|m| m.name.clone())
+        .collect::<HashSet<_>>();
+
+    // Update the version field. A virtual workspace without a shared version
+    // only has versions in its members.
+    if parsed_toml.get("package").is_some() || workspace_version(&parsed_toml).is_some() {
+        update_version_in_toml(&mut parsed_toml, new_version)?;
+    }
+    update_dependency_versions_in_toml(&mut parsed_toml, &member_names, new_version);
 
     // Serialize the updated TOML back to a string
     let updated_cargo_toml = parsed_toml.to_string();
@@ -129,6 +443,20 @@ pub fn update_version(tree: &WorkingTree, new_version: &str) -> Result<(), Error
     // Write the updated TOML back to Cargo.toml
     tree.put_file_bytes_non_atomic(Path::new("Cargo.toml"), updated_cargo_toml.as_bytes())?;
 
+    for member in members.iter().filter(|m| m.path != Path::new(".")) {
+        let path = member.path.join("Cargo.toml");
+        let mut parsed_toml = parse_toml_file(tree, &path)?;
+        if let Some(version) = parsed_toml
+            .get_mut("package")
+            .and_then(|p| p.get_mut("version"))
+            .filter(|v| v.is_str())
+        {
+            *version = toml_edit::value(new_version);
+        }
+        update_dependency_versions_in_toml(&mut parsed_toml, &member_names, new_version);
+        tree.put_file_bytes_non_atomic(&path, parsed_toml.to_string().as_bytes())?;
+    }
+
     // If there is a Cargo.lock file, then run `cargo update -w` to update the version in it
     if tree.has_filename(Path::new("Cargo.lock")) {
         Command::new("cargo")
@@ -187,6 +515,29 @@ pub fn find_version_in_toml(cargo_toml_contents: &str) -> Result<crate::version:
         .map_err(|e| Error::VersionError(format!("Unable to parse version: {}", e)))
 }
 
+pub fn find_name_in_toml(cargo_toml_contents: &str) -> Result<String, Error> {
+    let parsed_toml: toml_edit::DocumentMut = cargo_toml_contents
+        .parse()
+        .map_err(|e| Error::Other(format!("Unable to parse Cargo.toml: {}", e)))?;
+
+    parsed_toml
+        .as_table()
+        .get("package")
+        .and_then(|p| p.get("name"))
+        .and_then(|n| n.as_str())
+        .map(|n| n.to_string())
+        .ok_or_else(|| Error::Other("Unable to find package name in Cargo.toml".to_string()))
+}
+
+pub fn find_name(tree: &dyn Tree) -> Result<String, Error> {
+    let cargo_toml_contents = tree.get_file_text(Path::new("Cargo.toml"))?;
+
+    find_name_in_toml(
+        std::str::from_utf8(cargo_toml_contents.as_slice())
+            .map_err(|e| Error::Other(format!("Unable to parse Cargo.toml as UTF-8: {}", e)))?,
+    )
+}
+
 // Define a function to find the version in the Cargo.toml file
 pub fn find_version(tree: &dyn Tree) -> Result<crate::version::Version, Error> {
     // Read the Cargo.toml file
@@ -199,8 +550,885 @@ pub fn find_version(tree: &dyn Tree) -> Result<crate::version::Version, Error> {
     )
 }
 
+fn parse_toml_file(tree: &dyn Tree, path: &Path) -> Result<toml_edit::DocumentMut, Error> {
+    let contents = tree.get_file_text(path)?;
+    String::from_utf8_lossy(contents.as_slice())
+        .parse()
+        .map_err(|e| Error::Other(format!("Unable to parse {}: {}", path.display(), e)))
+}
+
+fn workspace_version(parsed_toml: &toml_edit::DocumentMut) -> Option<&str> {
+    parsed_toml
+        .get("workspace")
+        .and_then(|w| w.get("package"))
+        .and_then(|p| p.get("version"))
+        .and_then(|v| v.as_str())
+}
+
+/// Tables that list dependencies which end up in the published crate.
+const DEPENDENCY_TABLES: &[&str] = &["dependencies", "build-dependencies"];
+
+/// Call `f` with every dependency table in a manifest, including
+/// dev-dependencies, target-specific tables and `workspace.dependencies`.
+fn for_each_dependency_table_mut(
+    parsed_toml: &mut toml_edit::DocumentMut,
+    f: &mut dyn FnMut(&mut dyn toml_edit::TableLike),
+) {
+    fn visit(
+        table: &mut dyn toml_edit::TableLike,
+        f: &mut dyn FnMut(&mut dyn toml_edit::TableLike),
+    ) {
+        for (key, item) in table.iter_mut() {
+            if DEPENDENCY_TABLES.contains(&key.get()) || key.get() == "dev-dependencies" {
+                if let Some(deps) = item.as_table_like_mut() {
+                    f(deps);
+                }
+            }
+        }
+    }
+
+    visit(parsed_toml.as_table_mut(), f);
+    if let Some(targets) = parsed_toml
+        .get_mut("target")
+        .and_then(|t| t.as_table_like_mut())
+    {
+        for (_, target) in targets.iter_mut() {
+            if let Some(target) = target.as_table_like_mut() {
+                visit(target, f);
+            }
+        }
+    }
+    if let Some(deps) = parsed_toml
+        .get_mut("workspace")
+        .and_then(|w| w.get_mut("dependencies"))
+        .and_then(|d| d.as_table_like_mut())
+    {
+        f(deps);
+    }
+}
+
+/// Update the version requirements of path dependencies on the named crates.
+///
+/// The comparison operator of the existing requirement is kept, so
+/// `"=0.1.0"` becomes `"=0.2.0"`. Wildcard requirements such as `"*"` or
+/// `"0.*"` already match any new version and are left alone.
+pub fn update_dependency_versions_in_toml(
+    parsed_toml: &mut toml_edit::DocumentMut,
+    names: &HashSet<String>,
+    new_version: &str,
+) {
+    for_each_dependency_table_mut(parsed_toml, &mut |deps| {
+        for (key, dep) in deps.iter_mut() {
+            let name = dep
+                .get("package")
+                .and_then(|p| p.as_str())
+                .unwrap_or(key.get())
+                .to_string();
+            if !names.contains(&name) || dep.get("path").is_none() {
+                continue;
+            }
+            let Some(version) = dep.get_mut("version").and_then(|v| v.as_value_mut()) else {
+                continue;
+            };
+            let Some(old) = version.as_str() else {
+                continue;
+            };
+            if old.contains('*') {
+                continue;
+            }
+            let prefix = &old[..old.find(|c: char| c.is_ascii_digit()).unwrap_or(0)];
+            let decor = version.decor().clone();
+            *version = format!("{}{}", prefix, new_version).into();
+            *version.decor_mut() = decor;
+        }
+    });
+}
+
+/// A crate in a Cargo workspace.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkspaceMember {
+    /// Directory of the crate, relative to the workspace root.
+    pub path: PathBuf,
+
+    pub name: String,
+
+    pub version: Option<String>,
+
+    /// Registries the crate may be published to, from `package.publish`.
+    /// `None` means any registry; `publish = false` gives an empty list.
+    pub publish: Option<Vec<String>>,
+
+    /// Names of the crates this one needs at build time, whether or not they
+    /// are in the workspace. Dev-dependencies are not included.
+    pub dependencies: Vec<String>,
+
+    /// Files from outside the crate directory that cargo copies to the top
+    /// of the crate, such as a `readme` or `license-file` inherited from the
+    /// workspace, as pairs of the name in the crate and the path in the tree.
+    pub external_files: Vec<(PathBuf, PathBuf)>,
+}
+
+impl WorkspaceMember {
+    /// Whether the crate may be published to a registry, or to crates.io if
+    /// `registry` is `None`.
+    pub fn may_publish_to(&self, registry: Option<&str>) -> bool {
+        match &self.publish {
+            None => true,
+            Some(registries) => registries
+                .iter()
+                .any(|r| r == registry.unwrap_or(CRATES_IO)),
+        }
+    }
+}
+
+/// Parse the manifest of a workspace member.
+///
+/// `root` is the manifest of the workspace root, used to resolve fields that
+/// are inherited from the workspace.
+pub fn parse_workspace_member(
+    path: &Path,
+    cargo_toml_contents: &str,
+    root: &toml_edit::DocumentMut,
+) -> Result<WorkspaceMember, Error> {
+    let parsed_toml: toml_edit::DocumentMut = cargo_toml_contents.parse().map_err(|e| {
+        Error::Other(format!(
+            "Unable to parse {}: {}",
+            path.join("Cargo.toml").display(),
+            e
+        ))
+    })?;
+    let package = parsed_toml.get("package").ok_or_else(|| {
+        Error::Other(format!(
+            "Unable to find package in {}",
+            path.join("Cargo.toml").display()
+        ))
+    })?;
+    let inherits = |item: &toml_edit::Item| {
+        item.get("workspace")
+            .and_then(|w| w.as_bool())
+            .unwrap_or(false)
+    };
+    let workspace_package = root.get("workspace").and_then(|w| w.get("package"));
+
+    let name = package
+        .get("name")
+        .and_then(|n| n.as_str())
+        .ok_or_else(|| {
+            Error::Other(format!(
+                "Unable to find package name in {}",
+                path.join("Cargo.toml").display()
+            ))
+        })?
+        .to_string();
+
+    let version = match package.get("version") {
+        Some(v) if inherits(v) => workspace_version(root).map(|v| v.to_string()),
+        Some(v) => v.as_str().map(|v| v.to_string()),
+        None => None,
+    };
+
+    let publish = match package.get("publish") {
+        Some(p) if inherits(p) => workspace_package.and_then(|p| p.get("publish")),
+        p => p,
+    };
+    let publish = match publish {
+        Some(p) if p.as_bool() == Some(false) => Some(vec![]),
+        Some(p) => p.as_array().map(|a| {
+            a.iter()
+                .filter_map(|r| r.as_str())
+                .map(|r| r.to_string())
+                .collect()
+        }),
+        None => None,
+    };
+
+    let mut tables = DEPENDENCY_TABLES
+        .iter()
+        .filter_map(|t| parsed_toml.get(t))
+        .collect::<Vec<_>>();
+    if let Some(targets) = parsed_toml.get("target").and_then(|t| t.as_table_like()) {
+        for (_, target) in targets.iter() {
+            tables.extend(DEPENDENCY_TABLES.iter().filter_map(|t| target.get(t)));
+        }
+    }
+    let workspace_deps = root.get("workspace").and_then(|w| w.get("dependencies"));
+    let mut dependencies = vec![];
+    for deps in tables.into_iter().filter_map(|t| t.as_table_like()) {
+        for (key, dep) in deps.iter() {
+            let dep = if inherits(dep) {
+                workspace_deps.and_then(|d| d.get(key)).unwrap_or(dep)
+            } else {
+                dep
+            };
+            let name = dep
+                .get("package")
+                .and_then(|p| p.as_str())
+                .unwrap_or(key)
+                .to_string();
+            if !dependencies.contains(&name) {
+                dependencies.push(name);
+            }
+        }
+    }
+
+    let mut external_files = vec![];
+    for key in ["readme", "license-file"] {
+        let (base, file) = match package.get(key) {
+            Some(f) if inherits(f) => (Path::new("."), workspace_package.and_then(|p| p.get(key))),
+            f => (path, f),
+        };
+        let Some(file) = file.and_then(|f| f.as_str()) else {
+            continue;
+        };
+        let source = normalize_path(&base.join(file));
+        if source.starts_with(normalize_path(path)) {
+            continue;
+        }
+        if let Some(file_name) = source.file_name() {
+            external_files.push((PathBuf::from(file_name), source));
+        }
+    }
+
+    Ok(WorkspaceMember {
+        path: path.to_path_buf(),
+        name,
+        version,
+        publish,
+        dependencies,
+        external_files,
+    })
+}
+
+/// Resolve `.` and `..` components of a relative path lexically.
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut ret = PathBuf::new();
+    for component in path.components() {
+        match component {
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => {
+                ret.pop();
+            }
+            c => ret.push(c),
+        }
+    }
+    ret
+}
+
+/// Find the crates in the workspace rooted at the top of `tree`.
+///
+/// A crate that is not part of a workspace is returned as the only member.
+pub fn workspace_members(tree: &WorkingTree) -> Result<Vec<WorkspaceMember>, Error> {
+    let root = parse_toml_file(tree, Path::new("Cargo.toml"))?;
+    let root_text = root.to_string();
+
+    let mut members = vec![];
+    if root.get("package").is_some() {
+        members.push(parse_workspace_member(Path::new("."), &root_text, &root)?);
+    }
+
+    let Some(workspace) = root.get("workspace") else {
+        return Ok(members);
+    };
+    let patterns = |key: &str| {
+        workspace
+            .get(key)
+            .and_then(|m| m.as_array())
+            .map(|a| a.iter().filter_map(|m| m.as_str()).collect::<Vec<_>>())
+            .unwrap_or_default()
+    };
+    let excluded = patterns("exclude")
+        .into_iter()
+        .map(PathBuf::from)
+        .collect::<Vec<_>>();
+    for pattern in patterns("members") {
+        let mut paths = crate::iter_glob(tree, pattern).collect::<Vec<_>>();
+        paths.sort();
+        for path in paths {
+            if path == Path::new("")
+                || path == Path::new(".")
+                || excluded.contains(&path)
+                || members.iter().any(|m: &WorkspaceMember| m.path == path)
+                || !tree.has_filename(&path.join("Cargo.toml"))
+            {
+                continue;
+            }
+            let contents = tree.get_file_text(&path.join("Cargo.toml"))?;
+            members.push(parse_workspace_member(
+                &path,
+                &String::from_utf8_lossy(contents.as_slice()),
+                &root,
+            )?);
+        }
+    }
+    Ok(members)
+}
+
+/// Order workspace members so that every crate comes after the workspace
+/// crates it depends on.
+///
+/// Crates that don't depend on each other keep their original order.
+pub fn publish_order(members: &[WorkspaceMember]) -> Result<Vec<&WorkspaceMember>, Error> {
+    let names = members.iter().map(|m| m.name.as_str()).collect::<Vec<_>>();
+    let mut done: HashSet<&str> = HashSet::new();
+    let mut ordered = vec![];
+    let mut pending = members.iter().collect::<Vec<_>>();
+    while !pending.is_empty() {
+        let ready = pending.iter().position(|m| {
+            m.dependencies
+                .iter()
+                .filter(|d| names.contains(&d.as_str()) && d.as_str() != m.name)
+                .all(|d| done.contains(d.as_str()))
+        });
+        match ready {
+            Some(i) => {
+                let member = pending.remove(i);
+                done.insert(member.name.as_str());
+                ordered.push(member);
+            }
+            None => {
+                return Err(Error::Other(format!(
+                    "Dependency cycle between workspace crates: {}",
+                    pending
+                        .iter()
+                        .map(|m| m.name.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )));
+            }
+        }
+    }
+    Ok(ordered)
+}
+
+/// How long to wait for a published crate to show up in the registry.
+pub const INDEX_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(600);
+
+const INDEX_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// Wait until a version of a crate is visible in the registry.
+pub async fn wait_for_version(
+    api_url: Option<&url::Url>,
+    name: &str,
+    version: &str,
+    timeout: std::time::Duration,
+) -> Result<(), Error> {
+    let start = std::time::Instant::now();
+    while !is_version_published(api_url, name, version).await? {
+        if start.elapsed() >= timeout {
+            return Err(Error::Other(format!(
+                "Timed out after {} seconds waiting for {} {} to appear in the registry",
+                timeout.as_secs(),
+                name,
+                version
+            )));
+        }
+        log::info!("Waiting for {} {} to appear in the registry", name, version);
+        tokio::time::sleep(INDEX_POLL_INTERVAL).await;
+    }
+    Ok(())
+}
+
+/// Publish all crates in a workspace, in dependency order.
+///
+/// Crates that may not be published to `registry` and versions that have
+/// already been published are skipped. After each upload this waits for the
+/// registry to list the new version, so that the crates depending on it can
+/// be published.
+pub async fn publish_workspace(
+    tree: &WorkingTree,
+    api_url: Option<&url::Url>,
+    registry: Option<&str>,
+) -> Result<(), Error> {
+    let members = workspace_members(tree)?;
+    let ordered = publish_order(&members)?;
+    let publishable = ordered
+        .iter()
+        .filter(|m| m.may_publish_to(registry))
+        .count();
+    for (i, member) in ordered
+        .iter()
+        .filter(|m| m.may_publish_to(registry))
+        .enumerate()
+    {
+        let version = member.version.as_deref().ok_or_else(|| {
+            Error::Other(format!("Unable to find version of crate {}", member.name))
+        })?;
+        if is_version_published(api_url, &member.name, version).await? {
+            log::info!(
+                "{} {} has already been published, skipping",
+                member.name,
+                version
+            );
+            continue;
+        }
+        log::info!("Publishing {} {}", member.name, version);
+        publish(tree, &member.path, registry)?;
+        if i + 1 < publishable {
+            wait_for_version(api_url, &member.name, version, INDEX_TIMEOUT).await?;
+        }
+    }
+    for member in ordered.iter().filter(|m| !m.may_publish_to(registry)) {
+        log::info!(
+            "Not publishing {} to {}, not allowed by package.publish",
+            member.name,
+            registry.unwrap_or(CRATES_IO)
+        );
+    }
+    Ok(())
+}
+
+/// A `cargo` packaging command failed.
+#[derive(Debug)]
+pub struct PackageFailed {
+    pub command: Vec<String>,
+    pub status: Option<std::process::ExitStatus>,
+    /// Combined stdout and stderr of cargo.
+    pub output: String,
+}
+
+impl std::fmt::Display for PackageFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "`{}` failed", self.command.join(" "))?;
+        if let Some(status) = self.status {
+            write!(f, " ({})", status)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for PackageFailed {}
+
+/// Run cargo in the root of the tree, returning its standard output.
+fn run_cargo(tree: &WorkingTree, args: &[String]) -> Result<String, PackageFailed> {
+    let command = std::iter::once("cargo".to_string())
+        .chain(args.iter().cloned())
+        .collect::<Vec<_>>();
+    log::debug!("Running {:?}", command);
+    let output = Command::new("cargo")
+        .args(args)
+        .current_dir(tree.basedir())
+        .stdin(std::process::Stdio::null())
+        .output()
+        .map_err(|e| PackageFailed {
+            command: command.clone(),
+            status: None,
+            output: e.to_string(),
+        })?;
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    if !output.status.success() {
+        return Err(PackageFailed {
+            command,
+            status: Some(output.status),
+            output: format!("{}{}", stdout, String::from_utf8_lossy(&output.stderr)),
+        });
+    }
+    Ok(stdout)
+}
+
+/// The first version of cargo that can publish several packages at once.
+const MULTI_PACKAGE_PUBLISH_VERSION: (u64, u64) = (1, 90);
+
+/// Parse the major and minor version from the output of `cargo --version`,
+/// e.g. "cargo 1.90.0 (840b83a10 2025-07-30)".
+fn parse_cargo_version(output: &str) -> Option<(u64, u64)> {
+    let (_, major, minor) =
+        lazy_regex::regex_captures!(r"^cargo ([0-9]+)\.([0-9]+)", output.trim())?;
+    Some((major.parse().ok()?, minor.parse().ok()?))
+}
+
+/// Run `cargo publish --dry-run` for the given workspace members, which
+/// should be in dependency order; see [`publish_order`].
+///
+/// With cargo 1.90 or later the crates are packaged together, so crates
+/// depending on other members can be checked before those members have been
+/// published. Older versions of cargo check one crate at a time, and can
+/// only package, not build, crates that depend on other members.
+pub fn publish_dry_run(
+    tree: &WorkingTree,
+    members: &[&WorkspaceMember],
+    registry: Option<&str>,
+) -> Result<(), PackageFailed> {
+    let mut args = vec!["publish".to_string(), "--dry-run".to_string()];
+    if let Some(registry) = registry {
+        args.push("--registry".to_string());
+        args.push(registry.to_string());
+    }
+    let cargo_version = parse_cargo_version(&run_cargo(tree, &["--version".to_string()])?);
+    if cargo_version.is_some_and(|v| v >= MULTI_PACKAGE_PUBLISH_VERSION) {
+        for member in members {
+            args.push("-p".to_string());
+            args.push(member.name.clone());
+        }
+        run_cargo(tree, &args)?;
+        return Ok(());
+    }
+
+    for member in members {
+        let mut args = args.clone();
+        args.push("-p".to_string());
+        args.push(member.name.clone());
+        if member
+            .dependencies
+            .iter()
+            .any(|d| d != &member.name && members.iter().any(|m| &m.name == d))
+        {
+            log::warn!(
+                "cargo {}.{} or later is needed to build {} against unpublished workspace crates; only packaging it",
+                MULTI_PACKAGE_PUBLISH_VERSION.0,
+                MULTI_PACKAGE_PUBLISH_VERSION.1,
+                member.name
+            );
+            args.push("--no-verify".to_string());
+        }
+        run_cargo(tree, &args)?;
+    }
+    Ok(())
+}
+
+/// List the files that `cargo package` would include for a workspace member,
+/// relative to the directory of the crate.
+pub fn package_file_list(
+    tree: &WorkingTree,
+    member: &WorkspaceMember,
+) -> Result<Vec<PathBuf>, PackageFailed> {
+    let output = run_cargo(
+        tree,
+        &[
+            "package".to_string(),
+            "--list".to_string(),
+            "-p".to_string(),
+            member.name.clone(),
+        ],
+    )?;
+    Ok(output
+        .lines()
+        .filter(|l| !l.is_empty())
+        .map(PathBuf::from)
+        .collect())
+}
+
+/// Files that cargo generates while packaging, rather than copying them
+/// from the source tree.
+const GENERATED_PACKAGE_FILES: &[&str] = &[".cargo_vcs_info.json", "Cargo.toml.orig", "Cargo.lock"];
+
+/// Find files that would be included in a crate but are not versioned.
+///
+/// `versioned` holds the versioned files of the tree, relative to its root;
+/// `package_files` the files in the crate for `member`.
+pub fn unversioned_package_files(
+    member: &WorkspaceMember,
+    package_files: &[PathBuf],
+    versioned: &[PathBuf],
+) -> Vec<PathBuf> {
+    package_files
+        .iter()
+        .filter(|p| {
+            !GENERATED_PACKAGE_FILES
+                .iter()
+                .any(|g| p.as_path() == Path::new(g))
+        })
+        .filter(|p| {
+            let path = match member.external_files.iter().find(|(name, _)| name == *p) {
+                Some((_, source)) => source.clone(),
+                None => normalize_path(&member.path.join(p)),
+            };
+            !versioned.contains(&path)
+        })
+        .cloned()
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
+    fn member(name: &str, dependencies: &[&str]) -> super::WorkspaceMember {
+        super::WorkspaceMember {
+            path: name.into(),
+            name: name.to_string(),
+            version: Some("0.1.0".to_string()),
+            publish: None,
+            dependencies: dependencies.iter().map(|d| d.to_string()).collect(),
+            external_files: vec![],
+        }
+    }
+
+    #[test]
+    fn test_parse_cargo_version() {
+        assert_eq!(
+            super::parse_cargo_version("cargo 1.90.0 (840b83a10 2025-07-30)\n"),
+            Some((1, 90))
+        );
+        assert_eq!(
+            super::parse_cargo_version("cargo 1.85.1-nightly (d73d2caf9 2024-12-31)"),
+            Some((1, 85))
+        );
+        assert_eq!(super::parse_cargo_version("error: no such command"), None);
+    }
+
+    #[test]
+    fn test_publish_order() {
+        let members = vec![
+            member("cli", &["core", "clap"]),
+            member("core", &["serde"]),
+            member("macros", &[]),
+            member("extra", &["cli", "macros"]),
+        ];
+        let order = super::publish_order(&members)
+            .unwrap()
+            .into_iter()
+            .map(|m| m.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(order, vec!["core", "cli", "macros", "extra"]);
+    }
+
+    #[test]
+    fn test_publish_order_cycle() {
+        let members = vec![member("a", &["b"]), member("b", &["a"]), member("c", &[])];
+        assert!(super::publish_order(&members).is_err());
+    }
+
+    #[test]
+    fn test_parse_workspace_member() {
+        let root: toml_edit::DocumentMut = r#"[workspace]
+members = ["crates/*"]
+package = { version = "1.2.0", publish = false }
+
+[workspace.dependencies]
+renamed = { package = "core", path = "crates/core", version = "1.2.0" }
+"#
+        .parse()
+        .unwrap();
+        let text = r#"[package]
+name = "cli"
+version = { workspace = true }
+
+[dependencies]
+renamed = { workspace = true }
+serde = "1"
+
+[dev-dependencies]
+tempfile = "3"
+
+[target.'cfg(unix)'.build-dependencies]
+cc = "1"
+"#;
+        let member =
+            super::parse_workspace_member(std::path::Path::new("crates/cli"), text, &root).unwrap();
+        assert_eq!(member.name, "cli");
+        assert_eq!(member.version.as_deref(), Some("1.2.0"));
+        assert!(member.may_publish_to(None));
+        assert_eq!(member.dependencies, vec!["core", "serde", "cc"]);
+
+        let text = "[package]\nname = \"internal\"\nversion = \"0.1.0\"\npublish = false\n";
+        let member =
+            super::parse_workspace_member(std::path::Path::new("internal"), text, &root).unwrap();
+        assert_eq!(member.version.as_deref(), Some("0.1.0"));
+        assert!(!member.may_publish_to(None));
+        assert!(!member.may_publish_to(Some("internal")));
+
+        let text = "[package]\nname = \"private\"\npublish = [\"internal\"]\n";
+        let member =
+            super::parse_workspace_member(std::path::Path::new("private"), text, &root).unwrap();
+        assert!(!member.may_publish_to(None));
+        assert!(member.may_publish_to(Some("internal")));
+
+        let text = "[package]\nname = \"inherited\"\npublish.workspace = true\n";
+        let member =
+            super::parse_workspace_member(std::path::Path::new("inherited"), text, &root).unwrap();
+        assert!(!member.may_publish_to(None));
+    }
+
+    #[test]
+    fn test_parse_workspace_member_external_files() {
+        let root: toml_edit::DocumentMut = r#"[workspace]
+members = ["crates/*"]
+package = { readme = "README.md", license-file = "COPYING" }
+"#
+        .parse()
+        .unwrap();
+        let text = r#"[package]
+name = "cli"
+readme.workspace = true
+license-file = "../../LICENSE"
+"#;
+        let member =
+            super::parse_workspace_member(std::path::Path::new("crates/cli"), text, &root).unwrap();
+        assert_eq!(
+            member.external_files,
+            vec![
+                ("README.md".into(), "README.md".into()),
+                ("LICENSE".into(), "LICENSE".into())
+            ]
+        );
+
+        let text = "[package]\nname = \"core\"\nreadme = \"docs/README.md\"\n";
+        let member =
+            super::parse_workspace_member(std::path::Path::new("crates/core"), text, &root)
+                .unwrap();
+        assert_eq!(member.external_files, vec![]);
+    }
+
+    #[test]
+    fn test_update_dependency_versions_in_toml() {
+        let text = r#"[workspace.dependencies]
+core = { path = "crates/core", version = "=0.1.0" }
+serde = "1"
+
+[dependencies]
+macros = { path = "../macros", version = "0.1.0" }
+other = { version = "0.1.0" }
+any = { path = "../any", version = "*" }
+
+[target.'cfg(unix)'.dependencies.renamed]
+package = "core"
+path = "../core"
+version = "^0.1"
+"#;
+        let mut parsed_toml: toml_edit::DocumentMut = text.parse().unwrap();
+        let names = ["core", "macros", "other", "any"]
+            .iter()
+            .map(|n| n.to_string())
+            .collect();
+        super::update_dependency_versions_in_toml(&mut parsed_toml, &names, "0.2.0");
+        assert_eq!(
+            parsed_toml.to_string(),
+            r#"[workspace.dependencies]
+core = { path = "crates/core", version = "=0.2.0" }
+serde = "1"
+
+[dependencies]
+macros = { path = "../macros", version = "0.2.0" }
+other = { version = "0.1.0" }
+any = { path = "../any", version = "*" }
+
+[target.'cfg(unix)'.dependencies.renamed]
+package = "core"
+path = "../core"
+version = "^0.2.0"
+"#
+        );
+    }
+
+    #[test]
+    fn test_unversioned_package_files() {
+        let versioned = [
+            "Cargo.toml",
+            "README.md",
+            "crates/a/Cargo.toml",
+            "crates/a/src/lib.rs",
+        ]
+        .iter()
+        .map(std::path::PathBuf::from)
+        .collect::<Vec<_>>();
+        let package_files = [
+            ".cargo_vcs_info.json",
+            "Cargo.lock",
+            "Cargo.toml",
+            "Cargo.toml.orig",
+            "README.md",
+            "src/generated.rs",
+            "src/lib.rs",
+        ]
+        .iter()
+        .map(std::path::PathBuf::from)
+        .collect::<Vec<_>>();
+        // The readme is inherited from the workspace root.
+        let mut a = member("crates/a", &[]);
+        a.external_files = vec![("README.md".into(), "README.md".into())];
+        assert_eq!(
+            super::unversioned_package_files(&a, &package_files, &versioned),
+            vec![std::path::PathBuf::from("src/generated.rs")]
+        );
+        assert_eq!(
+            super::unversioned_package_files(&member(".", &[]), &package_files, &versioned),
+            vec![
+                std::path::PathBuf::from("src/generated.rs"),
+                std::path::PathBuf::from("src/lib.rs")
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_find_registry_index_in_config() {
+        let text = r#"[registries]
+internal = { index = "sparse+https://cargo.example.com/index/" }
+
+[registries.other]
+index = "https://git.example.com/index.git"
+"#;
+        assert_eq!(
+            super::find_registry_index_in_config(text, "internal").as_deref(),
+            Some("sparse+https://cargo.example.com/index/")
+        );
+        assert_eq!(
+            super::find_registry_index_in_config(text, "other").as_deref(),
+            Some("https://git.example.com/index.git")
+        );
+        assert_eq!(super::find_registry_index_in_config(text, "missing"), None);
+        assert!(super::registry_api_url("https://git.example.com/index.git")
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn test_owned_crate_in_monorepo() {
+        assert_eq!(
+            super::owned_crate(
+                "breezyshim-macros",
+                Some("https://github.com/jelmer/breezyshim/tree/main/crates/macros")
+            ),
+            Some(super::OwnedCrate {
+                name: "breezyshim-macros".to_string(),
+                repository: "https://github.com/jelmer/breezyshim".parse().unwrap(),
+                subpath: Some("crates/macros".into()),
+            })
+        );
+        assert_eq!(super::owned_crate("disperse", None), None);
+    }
+
+    #[test]
+    fn test_normalize_repository_url() {
+        let normalize = |s| {
+            super::normalize_repository_url(s)
+                .map(|(u, p): (url::Url, Option<std::path::PathBuf>)| (u.to_string(), p))
+        };
+        assert_eq!(
+            normalize("https://github.com/jelmer/disperse"),
+            Some(("https://github.com/jelmer/disperse".to_string(), None))
+        );
+        assert_eq!(
+            normalize("https://github.com/jelmer/disperse.git/"),
+            Some(("https://github.com/jelmer/disperse".to_string(), None))
+        );
+        assert_eq!(
+            normalize("git+https://github.com/jelmer/disperse.git#main"),
+            Some(("https://github.com/jelmer/disperse".to_string(), None))
+        );
+        assert_eq!(
+            normalize("https://github.com/jelmer/breezyshim/tree/main/crates/macros"),
+            Some((
+                "https://github.com/jelmer/breezyshim".to_string(),
+                Some("crates/macros".into())
+            ))
+        );
+        assert_eq!(
+            normalize("https://gitlab.com/group/project/-/tree/master/sub"),
+            Some((
+                "https://gitlab.com/group/project".to_string(),
+                Some("sub".into())
+            ))
+        );
+        assert_eq!(
+            normalize("https://github.com/jelmer/disperse/tree/main"),
+            Some(("https://github.com/jelmer/disperse".to_string(), None))
+        );
+        assert_eq!(
+            normalize("git@github.com:jelmer/disperse.git"),
+            Some(("https://github.com/jelmer/disperse".to_string(), None))
+        );
+        assert_eq!(normalize("not a url"), None);
+        assert_eq!(normalize("mailto:jelmer@example.com"), None);
+    }
+
     #[test]
     fn test_find_version_in_toml() {
         let text = "[package]\nversion = \"0.1.0\"\n";
@@ -214,6 +1442,15 @@ mod tests {
         assert_eq!(version, "0.2.0".parse().unwrap());
     }
 
+    #[test]
+    fn test_find_name_in_toml() {
+        let text = "[package]\nname = \"disperse\"\nversion = \"0.1.0\"\n";
+        assert_eq!(super::find_name_in_toml(text).unwrap(), "disperse");
+
+        let text = "[workspace]\nmembers = [\"a\"]\n";
+        assert!(super::find_name_in_toml(text).is_err());
+    }
+
     #[test]
     fn test_find_version_in_toml_error() {
         let text = "[package]\nversion = 0.1.0\n";
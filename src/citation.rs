@@ -0,0 +1,177 @@
+use crate::Version;
+use breezyshim::tree::MutableTree;
+use chrono::NaiveDate;
+use std::path::Path;
+
+pub const CITATION_FILE: &str = "CITATION.cff";
+
+#[derive(Debug)]
+pub enum Error {
+    BrzError(Box<breezyshim::error::Error>),
+    InvalidData(String),
+}
+
+impl From<breezyshim::error::Error> for Error {
+    fn from(e: breezyshim::error::Error) -> Self {
+        Error::BrzError(Box::new(e))
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self {
+            Error::BrzError(e) => write!(f, "Tree error: {}", e),
+            Error::InvalidData(e) => write!(f, "Invalid CITATION.cff: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Set the top-level `version` and `date-released` keys in a CITATION.cff file.
+///
+/// Keys that are missing are appended to the end of the file. The quoting
+/// style of existing values is preserved.
+pub fn update_citation_cff(
+    text: &str,
+    new_version: &Version,
+    release_date: NaiveDate,
+) -> Result<String, Error> {
+    if !text.lines().any(|l| l.starts_with("cff-version:")) {
+        return Err(Error::InvalidData("missing cff-version".to_string()));
+    }
+
+    let mut values = vec![
+        ("version", new_version.to_string(), false),
+        (
+            "date-released",
+            release_date.format("%Y-%m-%d").to_string(),
+            false,
+        ),
+    ];
+
+    let mut lines = text
+        .split_inclusive('\n')
+        .map(|line| {
+            for (key, value, seen) in values.iter_mut() {
+                let Some(old) = line
+                    .strip_prefix(*key)
+                    .and_then(|rest| rest.strip_prefix(':'))
+                else {
+                    continue;
+                };
+                *seen = true;
+                let old = old.trim();
+                let quote = match old.chars().next() {
+                    Some(q @ ('"' | '\'')) => q.to_string(),
+                    _ => String::new(),
+                };
+                let eol = if line.ends_with('\n') { "\n" } else { "" };
+                return format!("{}: {}{}{}{}", key, quote, value, quote, eol);
+            }
+            line.to_string()
+        })
+        .collect::<Vec<_>>();
+
+    for (key, value, seen) in values {
+        if seen {
+            continue;
+        }
+        if let Some(last) = lines.last_mut() {
+            if !last.ends_with('\n') {
+                last.push('\n');
+            }
+        }
+        lines.push(format!("{}: \"{}\"\n", key, value));
+    }
+
+    Ok(lines.concat())
+}
+
+/// Update the version and release date in a CITATION.cff file in a tree.
+pub fn update_citation_cff_in_tree(
+    tree: &dyn MutableTree,
+    path: &Path,
+    new_version: &Version,
+    release_date: NaiveDate,
+) -> Result<(), Error> {
+    let text = tree.get_file_text(path)?;
+    let text = String::from_utf8(text).map_err(|e| Error::InvalidData(e.to_string()))?;
+    let updated = update_citation_cff(&text, new_version, release_date)?;
+    tree.put_file_bytes_non_atomic(path, updated.as_bytes())?;
+    Ok(())
+}
+
+/// Validate that a CITATION.cff file can be updated.
+pub fn validate_citation_cff(tree: &dyn breezyshim::tree::Tree, path: &Path) -> Result<(), Error> {
+    let text = tree.get_file_text(path)?;
+    let text = String::from_utf8(text).map_err(|e| Error::InvalidData(e.to_string()))?;
+    update_citation_cff(
+        &text,
+        &Version {
+            major: 0,
+            minor: Some(0),
+            micro: Some(0),
+        },
+        chrono::Utc::now().date_naive(),
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_citation_cff() {
+        let text =
+            "cff-version: 1.2.0\ntitle: disperse\nversion: 0.1.0\ndate-released: '2019-12-31'\n";
+        assert_eq!(
+            update_citation_cff(
+                text,
+                &"1.2.3".parse().unwrap(),
+                NaiveDate::from_ymd_opt(2024, 1, 5).unwrap()
+            )
+            .unwrap(),
+            "cff-version: 1.2.0\ntitle: disperse\nversion: 1.2.3\ndate-released: '2024-01-05'\n"
+        );
+    }
+
+    #[test]
+    fn test_update_citation_cff_missing_keys() {
+        let text = "cff-version: 1.2.0\ntitle: disperse";
+        assert_eq!(
+            update_citation_cff(
+                text,
+                &"1.2.3".parse().unwrap(),
+                NaiveDate::from_ymd_opt(2024, 1, 5).unwrap()
+            )
+            .unwrap(),
+            "cff-version: 1.2.0\ntitle: disperse\nversion: \"1.2.3\"\ndate-released: \"2024-01-05\"\n"
+        );
+    }
+
+    #[test]
+    fn test_update_citation_cff_nested_version() {
+        let text = "cff-version: 1.2.0\nreferences:\n  - version: 0.5\nversion: 0.1.0\n";
+        assert_eq!(
+            update_citation_cff(
+                text,
+                &"1.2.3".parse().unwrap(),
+                NaiveDate::from_ymd_opt(2024, 1, 5).unwrap()
+            )
+            .unwrap(),
+            "cff-version: 1.2.0\nreferences:\n  - version: 0.5\nversion: 1.2.3\ndate-released: \"2024-01-05\"\n"
+        );
+    }
+
+    #[test]
+    fn test_update_citation_cff_invalid() {
+        assert!(update_citation_cff(
+            "title: disperse\n",
+            &"1.2.3".parse().unwrap(),
+            NaiveDate::from_ymd_opt(2024, 1, 5).unwrap()
+        )
+        .is_err());
+    }
+}
@@ -10,9 +10,25 @@ const CONFIG_FILE_NAME: &str = "disperse.toml";
 /// ```toml
 /// [pypi]
 /// username = "jelmer"
+/// repository-url = "https://upload.pypi.org/legacy/"
+/// index-url = "https://pypi.org/"
+/// projects = ["dulwich"]
 ///
 /// [crates.io]
 /// username = "jelmer"
+/// api-url = "https://crates.io/api/v1/"
+///
+/// [cargo-registries.internal]
+/// username = "jelmer"
+/// api-url = "https://cargo.example.com/api/v1/"
+///
+/// [github-hosts."github.example.com"]
+/// api-url = "https://github.example.com/api/v3/"
+/// token = "ghp_..."
+///
+/// [github-hosts."github.com"]
+/// app-id = 12345
+/// private-key-file = "/etc/disperse/app.pem"
 /// ```
 
 #[derive(Debug, serde::Deserialize, Default)]
@@ -20,7 +36,17 @@ pub struct Config {
     pub pypi: Option<PypiConfig>,
     #[serde(rename = "crates.io")]
     pub crates_io: Option<CratesIoConfig>,
+    /// Alternative Cargo registries, by the name used in `[registries]`.
+    #[serde(rename = "cargo-registries")]
+    pub cargo_registries: Option<std::collections::HashMap<String, CratesIoConfig>>,
     pub repositories: Option<RepositoriesConfig>,
+    /// GitHub Enterprise Server instances, by host name.
+    #[serde(rename = "github-hosts")]
+    pub github_hosts: Option<std::collections::HashMap<String, GitHubHostConfig>>,
+    /// Fail rather than prompt for missing credentials. This is implied if
+    /// standard input is not a terminal.
+    #[serde(rename = "non-interactive", default)]
+    pub non_interactive: bool,
 }
 
 #[derive(Debug, Deserialize)]
@@ -30,12 +56,51 @@ pub struct RepositoriesConfig {
 
 #[derive(Debug, Deserialize)]
 pub struct PypiConfig {
-    pub username: String,
+    pub username: Option<String>,
+    /// Upload endpoint to use instead of PyPI.
+    #[serde(rename = "repository-url")]
+    pub repository_url: Option<String>,
+    /// Index to check for already published releases.
+    #[serde(rename = "index-url")]
+    pub index_url: Option<url::Url>,
+    /// Projects to discover. PyPI has no API for listing the projects of
+    /// `username`, so they have to be listed here.
+    pub projects: Option<Vec<String>>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct CratesIoConfig {
-    pub username: String,
+    pub username: Option<String>,
+    /// Web API of the registry, for checking for already published versions.
+    #[serde(rename = "api-url")]
+    pub api_url: Option<url::Url>,
+}
+
+/// Credentials and endpoints for a GitHub host.
+///
+/// Credentials are looked up in order: the `GITHUB_TOKEN` environment
+/// variable (github.com only), the keyring, `token` or `token-file`, and
+/// finally the GitHub App settings.
+#[derive(Debug, Deserialize, Default)]
+pub struct GitHubHostConfig {
+    /// REST API endpoint; defaults to `https://<host>/api/v3/`.
+    #[serde(rename = "api-url")]
+    pub api_url: Option<url::Url>,
+    /// Personal access token for this host.
+    pub token: Option<String>,
+    /// File containing the personal access token.
+    #[serde(rename = "token-file")]
+    pub token_file: Option<std::path::PathBuf>,
+    /// ID of a GitHub App to authenticate as.
+    #[serde(rename = "app-id")]
+    pub app_id: Option<u64>,
+    /// PEM-encoded private key of the GitHub App.
+    #[serde(rename = "private-key-file")]
+    pub private_key_file: Option<std::path::PathBuf>,
+    /// Installation of the GitHub App to use; looked up for each repository
+    /// if unset.
+    #[serde(rename = "installation-id")]
+    pub installation_id: Option<u64>,
 }
 
 pub fn load_config() -> Result<Option<Config>, Box<dyn std::error::Error>> {
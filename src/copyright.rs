@@ -0,0 +1,262 @@
+use breezyshim::tree::MutableTree;
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum Error {
+    BrzError(Box<breezyshim::error::Error>),
+    NoMatches,
+}
+
+impl From<breezyshim::error::Error> for Error {
+    fn from(e: breezyshim::error::Error) -> Self {
+        Error::BrzError(Box::new(e))
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self {
+            Error::BrzError(e) => write!(f, "TreeError: {}", e),
+            Error::NoMatches => write!(f, "No matching copyright notices found"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Extend a list of years such as "2019-2023" or "2019, 2021" to include `year`.
+///
+/// A trailing range is extended; a list of single years gets `year` appended
+/// to it, so "2019, 2021" becomes "2019, 2021, 2024".
+fn extend_years(years: &str, year: i32) -> Option<String> {
+    let (_, head, last) =
+        lazy_regex::regex_captures!(r"^(.*?)([0-9]{4}(?:\s*[-–]\s*[0-9]{4})?)$", years)?;
+
+    let updated = if let Some((_, start, sep, end)) =
+        lazy_regex::regex_captures!(r"^([0-9]{4})(\s*[-–]\s*)([0-9]{4})$", last)
+    {
+        if end.parse::<i32>().ok()? >= year {
+            return None;
+        }
+        format!("{}{}{}", start, sep, year)
+    } else {
+        if last.parse::<i32>().ok()? >= year {
+            return None;
+        }
+        if head.is_empty() {
+            format!("{}-{}", last, year)
+        } else {
+            format!("{}, {}", last, year)
+        }
+    };
+
+    Some(format!("{}{}", head, updated))
+}
+
+/// Update the copyright notices in `text` to include `year`.
+///
+/// Only the notices that name `holder` are updated, or if no holder is
+/// given, only the first notice; other notices, such as the one for the
+/// license text itself in a LICENSE file, are left alone.
+///
+/// Returns the updated text and the number of copyright notices updated,
+/// including those that were already up to date.
+pub fn update_copyright_years(text: &str, year: i32, holder: Option<&str>) -> (String, usize) {
+    let holder = holder.map(|h| h.to_lowercase());
+    let mut found = 0;
+    let updated = lazy_regex::regex_replace_all!(
+        r"(?im)((?:copyright|\(c\)|©)[^0-9\n]*?)([0-9]{4}(?:\s*[-–]\s*[0-9]{4})?(?:\s*,\s*[0-9]{4}(?:\s*[-–]\s*[0-9]{4})?)*)([^\n]*)",
+        text,
+        |notice: &str, prefix: &str, years: &str, rest: &str| {
+            let matches = match holder.as_ref() {
+                Some(holder) => rest.to_lowercase().contains(holder.as_str()),
+                None => found == 0,
+            };
+            if !matches {
+                return notice.to_string();
+            }
+            found += 1;
+            match extend_years(years, year) {
+                Some(years) => format!("{}{}{}", prefix, years, rest),
+                None => notice.to_string(),
+            }
+        }
+    );
+    (updated.into_owned(), found)
+}
+
+/// Update the copyright notices in a file to include `year`.
+pub fn update_copyright_in_file(
+    tree: &dyn MutableTree,
+    path: &Path,
+    year: i32,
+    holder: Option<&str>,
+) -> Result<(), Error> {
+    let text = tree.get_file_text(path)?;
+    let text = String::from_utf8_lossy(&text);
+
+    let (updated, found) = update_copyright_years(&text, year, holder);
+    if found == 0 {
+        return Err(Error::NoMatches);
+    }
+
+    if updated != text {
+        log::info!("Updating copyright years in {}", path.display());
+        tree.put_file_bytes_non_atomic(path, updated.as_bytes())?;
+    }
+
+    Ok(())
+}
+
+/// Strip the e-mail address from an author such as "Jane Doe <jane@example.com>".
+fn author_name(author: &str) -> String {
+    author
+        .split('<')
+        .next()
+        .unwrap_or(author)
+        .trim()
+        .to_string()
+}
+
+/// Find the first author of the project, from `Cargo.toml` or
+/// `pyproject.toml`, to use as the copyright holder.
+pub fn find_project_author(tree: &dyn breezyshim::tree::Tree) -> Option<String> {
+    let parse = |path: &str| -> Option<toml_edit::DocumentMut> {
+        let text = tree.get_file_text(Path::new(path)).ok()?;
+        String::from_utf8_lossy(&text).parse().ok()
+    };
+    if let Some(cargo_toml) = parse("Cargo.toml") {
+        let authors = match cargo_toml.get("package").and_then(|p| p.get("authors")) {
+            Some(a) if a.get("workspace").and_then(|w| w.as_bool()) == Some(true) => cargo_toml
+                .get("workspace")
+                .and_then(|w| w.get("package"))
+                .and_then(|p| p.get("authors")),
+            a => a,
+        };
+        if let Some(author) = authors
+            .and_then(|a| a.as_array())
+            .and_then(|a| a.get(0))
+            .and_then(|a| a.as_str())
+        {
+            return Some(author_name(author));
+        }
+    }
+    let pyproject = parse("pyproject.toml")?;
+    pyproject
+        .get("project")
+        .and_then(|p| p.get("authors"))
+        .and_then(|a| a.as_array())
+        .and_then(|a| a.get(0))
+        .and_then(|a| a.as_inline_table())
+        .and_then(|a| a.get("name"))
+        .and_then(|n| n.as_str())
+        .map(author_name)
+}
+
+/// Validate that a file contains copyright notices that can be updated.
+pub fn validate_update_copyright(
+    tree: &dyn breezyshim::tree::Tree,
+    path: &Path,
+    holder: Option<&str>,
+) -> Result<(), Error> {
+    let text = tree.get_file_text(path)?;
+
+    match update_copyright_years(&String::from_utf8_lossy(&text), 0, holder) {
+        (_, 0) => Err(Error::NoMatches),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{author_name, update_copyright_years};
+
+    #[test]
+    fn test_author_name() {
+        assert_eq!(
+            author_name("Jelmer Vernooĳ <jelmer@jelmer.uk>"),
+            "Jelmer Vernooĳ"
+        );
+        assert_eq!(author_name("Jelmer Vernooĳ"), "Jelmer Vernooĳ");
+    }
+
+    #[test]
+    fn test_range() {
+        assert_eq!(
+            update_copyright_years("# Copyright (C) 2019-2023 Jelmer Vernooĳ\n", 2024, None),
+            ("# Copyright (C) 2019-2024 Jelmer Vernooĳ\n".to_string(), 1)
+        );
+    }
+
+    #[test]
+    fn test_single_year() {
+        assert_eq!(
+            update_copyright_years("Copyright 2023 Jelmer\n", 2024, None),
+            ("Copyright 2023-2024 Jelmer\n".to_string(), 1)
+        );
+        assert_eq!(
+            update_copyright_years("© 2023 Jelmer\n", 2024, None),
+            ("© 2023-2024 Jelmer\n".to_string(), 1)
+        );
+    }
+
+    #[test]
+    fn test_list() {
+        assert_eq!(
+            update_copyright_years("Copyright (c) 2019, 2021 Jelmer\n", 2024, None),
+            ("Copyright (c) 2019, 2021, 2024 Jelmer\n".to_string(), 1)
+        );
+        assert_eq!(
+            update_copyright_years("Copyright (c) 2019, 2021-2023 Jelmer\n", 2024, None),
+            ("Copyright (c) 2019, 2021-2024 Jelmer\n".to_string(), 1)
+        );
+    }
+
+    #[test]
+    fn test_up_to_date() {
+        assert_eq!(
+            update_copyright_years(
+                "Copyright (C) 2019-2024 Jelmer\nCopyright 2024 Foo\n",
+                2024,
+                None
+            ),
+            (
+                "Copyright (C) 2019-2024 Jelmer\nCopyright 2024 Foo\n".to_string(),
+                1
+            )
+        );
+    }
+
+    #[test]
+    fn test_holder() {
+        let text = "Copyright (C) 2019 Jelmer Vernooĳ\nCopyright (C) 2020 Foo Bar\n";
+        assert_eq!(
+            update_copyright_years(text, 2024, Some("foo bar")),
+            (
+                "Copyright (C) 2019 Jelmer Vernooĳ\nCopyright (C) 2020-2024 Foo Bar\n".to_string(),
+                1
+            )
+        );
+        assert_eq!(update_copyright_years(text, 2024, Some("Baz")).1, 0);
+    }
+
+    #[test]
+    fn test_license_text() {
+        let text = "GNU GENERAL PUBLIC LICENSE\n\
+Copyright (C) 2007 Free Software Foundation, Inc. <https://fsf.org/>\n\
+\n\
+Copyright (C) 2019-2023 Jelmer Vernooĳ\n";
+        assert_eq!(
+            update_copyright_years(text, 2024, Some("Jelmer Vernooĳ")),
+            (text.replace("2019-2023", "2019-2024"), 1)
+        );
+    }
+
+    #[test]
+    fn test_no_notice() {
+        assert_eq!(
+            update_copyright_years("Released in 2019\n", 2024, None),
+            ("Released in 2019\n".to_string(), 0)
+        );
+    }
+}
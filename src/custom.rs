@@ -1,3 +1,4 @@
+use crate::project_config::Occurrences;
 use crate::{Status, Version};
 use maplit::hashmap;
 use std::collections::HashMap;
@@ -200,15 +201,123 @@ pub fn extract_version(line: &str) -> (Option<Version>, Option<Status>) {
 }
 
 pub fn reverse_version(new_line: &str, lines: &[&str]) -> (Option<Version>, Option<Status>) {
+    reverse_version_in_text(new_line, lines.join("\n").as_str(), Occurrences::First)
+}
+
+/// Extracts the version and status from the selected occurrence of `new_line` in `text`.
+///
+/// If multiple occurrences are selected, the first one is used.
+pub fn reverse_version_in_text(
+    new_line: &str,
+    text: &str,
+    occurrences: Occurrences,
+) -> (Option<Version>, Option<Status>) {
     let re = version_line_re(new_line);
-    for line in lines {
-        let cm = re.captures_iter(line);
-        let (v, s) = version_from_capture_matches(cm);
-        if v.is_some() {
-            return (v, s);
+    let spans = matching_line_spans(&re, text, new_line.contains('\n'));
+    if let Some(span) = occurrences.select(spans).into_iter().next() {
+        version_from_capture_matches(re.captures_iter(&text[span]))
+    } else {
+        (None, None)
+    }
+}
+
+/// Find the spans of the lines in `text` that are matched by `r`.
+///
+/// Unless `across_lines` is set, `r` is matched against each line on its
+/// own. Otherwise it is matched against the whole text, and each span covers
+/// all of the line(s) that a match touches, so a pattern that spans multiple
+/// lines yields a single span. Further matches that start on a line that is
+/// already covered are merged into the preceding span. Spans exclude the
+/// final newline.
+fn matching_line_spans(
+    r: &regex::Regex,
+    text: &str,
+    across_lines: bool,
+) -> Vec<std::ops::Range<usize>> {
+    let mut spans: Vec<std::ops::Range<usize>> = vec![];
+    if !across_lines {
+        let mut start = 0;
+        for line in text.split_inclusive('\n') {
+            let content = line.strip_suffix('\n').unwrap_or(line);
+            if r.is_match(content) {
+                spans.push(start..start + content.len());
+            }
+            start += line.len();
+        }
+        return spans;
+    }
+    for m in r.find_iter(text) {
+        let start = text[..m.start()].rfind('\n').map_or(0, |i| i + 1);
+        let end = if m.as_str().ends_with('\n') {
+            m.end() - 1
+        } else {
+            text[m.end()..]
+                .find('\n')
+                .map_or(text.len(), |i| m.end() + i)
+        };
+        match spans.last_mut() {
+            Some(last) if start <= last.end => {
+                last.end = last.end.max(end);
+            }
+            _ => spans.push(start..end),
         }
     }
-    (None, None)
+    spans
+}
+
+#[cfg(test)]
+mod matching_line_spans_tests {
+    fn spans(pattern: &str, text: &str) -> Vec<String> {
+        let re = regex::RegexBuilder::new(pattern)
+            .multi_line(true)
+            .build()
+            .unwrap();
+        super::matching_line_spans(&re, text, true)
+            .into_iter()
+            .map(|s| text[s].to_string())
+            .collect()
+    }
+
+    fn line_spans(pattern: &str, text: &str) -> Vec<String> {
+        let re = regex::Regex::new(pattern).unwrap();
+        super::matching_line_spans(&re, text, false)
+            .into_iter()
+            .map(|s| text[s].to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_whole_lines() {
+        assert_eq!(
+            spans("1.2", "a = 1.2\nb = 1.3\nc = 1.2 or 1.2\n"),
+            vec!["a = 1.2", "c = 1.2 or 1.2"]
+        );
+        assert_eq!(
+            line_spans("1.2", "a = 1.2\nb = 1.3\nc = 1.2 or 1.2"),
+            vec!["a = 1.2", "c = 1.2 or 1.2"]
+        );
+    }
+
+    #[test]
+    fn test_per_line() {
+        assert_eq!(
+            line_spans(r"^version\s*=.*$", "version\n= 1\nversion = 2\n"),
+            vec!["version = 2"]
+        );
+    }
+
+    #[test]
+    fn test_multi_line() {
+        assert_eq!(
+            spans(r"(?s)^v = \(.*?\)$", "x\nv = (\n  1,\n  2,\n)\ny\n"),
+            vec!["v = (\n  1,\n  2,\n)"]
+        );
+    }
+
+    #[test]
+    fn test_trailing_newline() {
+        assert_eq!(spans("^b.*\n", "a\nb\nc\n"), vec!["b"]);
+    }
 }
 
 #[cfg(test)]
@@ -237,46 +346,247 @@ mod reverse_version_tests {
         assert_eq!(v, Some(super::Version::from_str("1.2.3").unwrap()));
         assert_eq!(s, Some(super::Status::Dev));
     }
+
+    #[test]
+    fn test_nth() {
+        let (v, s) = super::reverse_version_in_text(
+            "version = $VERSION",
+            "version = 1.2.3\nversion = 1.2.4\n",
+            super::Occurrences::Nth(2),
+        );
+        assert_eq!(v, Some(super::Version::from_str("1.2.4").unwrap()));
+        assert_eq!(s, None);
+    }
+
+    #[test]
+    fn test_multi_line() {
+        let (v, _) = super::reverse_version_in_text(
+            "__version__ = (\n    $MAJOR_VERSION,\n    $MINOR_VERSION,\n)",
+            "x = 1\n__version__ = (\n    1,\n    2,\n)\n",
+            super::Occurrences::All,
+        );
+        assert_eq!(v, Some(super::Version::from_str("1.2").unwrap()));
+    }
 }
 
-pub fn update_version_in_file(
-    tree: &dyn breezyshim::tree::MutableTree,
-    path: &std::path::Path,
+/// Whether an `update_version` pattern is matched against the whole text
+/// rather than line by line.
+///
+/// A `match` pattern only spans lines if `multi_line` is set; the pattern
+/// derived from `new_line` does if `new_line` does.
+fn matches_across_lines(new_line: &str, r#match: Option<&str>, multi_line: bool) -> bool {
+    match r#match {
+        Some(_) => multi_line,
+        None => multi_line || new_line.contains('\n'),
+    }
+}
+
+fn update_version_re(
     new_line: &str,
     r#match: Option<&str>,
+    multi_line: bool,
+) -> Result<regex::Regex, String> {
+    if let Some(m) = r#match {
+        regex::RegexBuilder::new(m)
+            .multi_line(multi_line)
+            .build()
+            .map_err(|e| format!("Invalid regex {}: {}", m, e))
+    } else {
+        Ok(version_line_re(new_line))
+    }
+}
+
+/// Replace the selected occurrences of a version line in `text`.
+///
+/// Every line matched by a selected match is replaced by `new_line`, with
+/// the version variables expanded. The pattern is matched against each line
+/// on its own, unless `multi_line` is set or `new_line` spans multiple lines
+/// and no `match` is given; see [`matches_across_lines`].
+pub fn update_version_in_text(
+    text: &str,
+    new_line: &str,
+    r#match: Option<&str>,
+    multi_line: bool,
+    occurrences: Occurrences,
     new_version: &Version,
     status: Status,
-) -> Result<(), String> {
-    let mut lines = tree.get_file_lines(path).unwrap();
-    let mut matches = 0;
-    let r = if let Some(m) = r#match {
-        regex::Regex::new(m).unwrap()
-    } else {
-        version_line_re(new_line)
-    };
-    log::debug!("Expanding {:?} in {:?}", r, path);
-    for oline in lines.iter_mut() {
-        let line = match std::str::from_utf8(oline) {
-            Ok(s) => s.trim_end_matches('\n'),
-            Err(_) => continue,
-        };
-        if !r.is_match(line) {
-            continue;
-        }
-        let uline = expand_version_vars(new_line, new_version, status).unwrap();
-        let uline = format!("{}\n", uline);
-        log::debug!("Expanded {:?} to {:?}", new_line, uline);
-        *oline = uline.into_bytes();
-        matches += 1;
+) -> Result<String, String> {
+    let r = update_version_re(new_line, r#match, multi_line)?;
+    let spans = matching_line_spans(
+        &r,
+        text,
+        matches_across_lines(new_line, r#match, multi_line),
+    );
+    if spans.is_empty() {
+        return Err(format!("No matches for {}", r.as_str()));
     }
-    if matches == 0 {
+    if spans.len() < occurrences.required_matches() {
         return Err(format!(
-            "No matches for {} in {}",
+            "Found {} matches for {}, but occurrences is {}",
+            spans.len(),
             r.as_str(),
-            path.display()
+            occurrences
         ));
     }
-    tree.put_file_bytes_non_atomic(path, lines.concat().as_slice())
+    let uline = expand_version_vars(new_line, new_version, status)?;
+    log::debug!("Expanded {:?} to {:?}", new_line, uline);
+    let mut ret = String::with_capacity(text.len());
+    let mut last = 0;
+    for span in occurrences.select(spans) {
+        ret.push_str(&text[last..span.start]);
+        ret.push_str(&uline);
+        last = span.end;
+    }
+    ret.push_str(&text[last..]);
+    Ok(ret)
+}
+
+#[cfg(test)]
+mod update_version_in_text_tests {
+    use super::{update_version_in_text, Occurrences, Status, Version};
+    use std::str::FromStr;
+
+    const TEXT: &str = "a\nversion = 1.2.3\nb\nversion = 1.2.3\n";
+
+    #[test]
+    fn test_all() {
+        assert_eq!(
+            update_version_in_text(
+                TEXT,
+                "version = $VERSION",
+                None,
+                false,
+                Occurrences::All,
+                &Version::from_str("1.2.4").unwrap(),
+                Status::Final,
+            )
+            .unwrap(),
+            "a\nversion = 1.2.4\nb\nversion = 1.2.4\n"
+        );
+    }
+
+    #[test]
+    fn test_first() {
+        assert_eq!(
+            update_version_in_text(
+                TEXT,
+                "version = $VERSION",
+                None,
+                false,
+                Occurrences::First,
+                &Version::from_str("1.2.4").unwrap(),
+                Status::Final,
+            )
+            .unwrap(),
+            "a\nversion = 1.2.4\nb\nversion = 1.2.3\n"
+        );
+    }
+
+    #[test]
+    fn test_nth() {
+        assert_eq!(
+            update_version_in_text(
+                TEXT,
+                "version = $VERSION",
+                None,
+                false,
+                Occurrences::Nth(2),
+                &Version::from_str("1.2.4").unwrap(),
+                Status::Final,
+            )
+            .unwrap(),
+            "a\nversion = 1.2.3\nb\nversion = 1.2.4\n"
+        );
+        assert!(update_version_in_text(
+            TEXT,
+            "version = $VERSION",
+            None,
+            false,
+            Occurrences::Nth(3),
+            &Version::from_str("1.2.4").unwrap(),
+            Status::Final,
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_multi_line() {
+        assert_eq!(
+            update_version_in_text(
+                "__version__ = (\n    1,\n    2,\n    3,\n)\n",
+                "__version__ = $TUPLED_VERSION",
+                Some(r"(?s)^__version__ = \(.*?\)$"),
+                true,
+                Occurrences::All,
+                &Version::from_str("1.2.4").unwrap(),
+                Status::Final,
+            )
+            .unwrap(),
+            "__version__ = (1, 2, 4)\n"
+        );
+    }
+
+    #[test]
+    fn test_match_per_line() {
+        // Without multi-line, \s* must not run into the next line.
+        assert_eq!(
+            update_version_in_text(
+                "version\n= x\nversion = 1.2.3\n",
+                "version = $VERSION",
+                Some(r"^version\s*=.*"),
+                false,
+                Occurrences::All,
+                &Version::from_str("1.2.4").unwrap(),
+                Status::Final,
+            )
+            .unwrap(),
+            "version\n= x\nversion = 1.2.4\n"
+        );
+    }
+
+    #[test]
+    fn test_no_matches() {
+        assert!(update_version_in_text(
+            "a\n",
+            "version = $VERSION",
+            None,
+            false,
+            Occurrences::All,
+            &Version::from_str("1.2.4").unwrap(),
+            Status::Final,
+        )
+        .is_err());
+    }
+}
+
+/// Update the version in a file, as configured by `update_version`.
+///
+/// The file has to be valid UTF-8.
+pub fn update_version_in_file(
+    tree: &dyn breezyshim::tree::MutableTree,
+    update_version: &crate::project_config::UpdateVersion,
+    new_version: &Version,
+    status: Status,
+) -> Result<(), String> {
+    let path = update_version.path.as_path();
+    let new_line = update_version.new_line.as_str();
+    let text = tree
+        .get_file_text(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let text = String::from_utf8(text)
+        .map_err(|e| format!("Invalid UTF-8 in {}: {}", path.display(), e))?;
+    log::debug!("Expanding {:?} in {:?}", new_line, path);
+    let text = update_version_in_text(
+        &text,
+        new_line,
+        update_version.r#match.as_deref(),
+        update_version.multi_line,
+        update_version.occurrences.unwrap_or_default(),
+        new_version,
+        status,
+    )
+    .map_err(|e| format!("{} in {}", e, path.display()))?;
+    tree.put_file_bytes_non_atomic(path, text.as_bytes())
         .unwrap();
     Ok(())
 }
@@ -298,9 +608,13 @@ mod tests {
         tree.add(&[std::path::Path::new("test")]).unwrap();
         super::update_version_in_file(
             &tree,
-            path,
-            "version = [$VERSION]",
-            None,
+            &crate::project_config::UpdateVersion {
+                path: path.to_path_buf(),
+                r#match: None,
+                new_line: "version = [$VERSION]".to_string(),
+                occurrences: None,
+                multi_line: false,
+            },
             &super::Version {
                 major: 1,
                 minor: Some(2),
@@ -319,32 +633,21 @@ pub fn validate_update_version(
 ) -> Result<(), String> {
     let path = &update_version.path;
 
-    let new_line = &update_version.new_line;
-
-    let mut lines = match wt.get_file_lines(std::path::Path::new(path)) {
+    let text = match wt.get_file_text(std::path::Path::new(path)) {
         Ok(l) => l,
         Err(breezyshim::error::Error::NoSuchFile(_)) => {
             return Err(format!("No such file: {}", path.display()))
         }
         Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
     };
-    let mut matches = 0;
-    let r = if let Some(m) = &update_version.r#match {
-        regex::Regex::new(m).unwrap()
-    } else {
-        version_line_re(new_line)
-    };
+    let text = String::from_utf8(text)
+        .map_err(|e| format!("Invalid UTF-8 in {}: {}", path.display(), e))?;
+    let r#match = update_version.r#match.as_deref();
+    let r = update_version_re(&update_version.new_line, r#match, update_version.multi_line)?;
     log::debug!("Expanding {:?} in {:?}", r, update_version.path);
-    for oline in lines.iter_mut() {
-        let line = match std::str::from_utf8(oline) {
-            Ok(s) => s.trim_end_matches('\n'),
-            Err(_) => continue,
-        };
-        if !r.is_match(line) {
-            continue;
-        }
-        matches += 1;
-    }
+    let across_lines =
+        matches_across_lines(&update_version.new_line, r#match, update_version.multi_line);
+    let matches = matching_line_spans(&r, &text, across_lines).len();
     if matches == 0 {
         return Err(format!(
             "No matches for {} in {}",
@@ -352,5 +655,15 @@ pub fn validate_update_version(
             path.display()
         ));
     }
+    let occurrences = update_version.occurrences.unwrap_or_default();
+    if matches < occurrences.required_matches() {
+        return Err(format!(
+            "Found {} matches for {} in {}, but occurrences is {}",
+            matches,
+            r.as_str(),
+            path.display(),
+            occurrences
+        ));
+    }
     Ok(())
 }
@@ -1,17 +1,45 @@
-use breezyshim::github::retrieve_github_token;
-use log::{debug, error, info};
-use octocrab::params::repos::Commitish;
+use crate::config::GitHubHostConfig;
+use log::{error, info};
 use octocrab::Octocrab;
+use std::collections::HashMap;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 use url::Url;
 
-const DEFAULT_GITHUB_CI_TIMEOUT: u64 = 60 * 24;
+/// How long to wait for CI, unless configured otherwise.
+const DEFAULT_GITHUB_CI_TIMEOUT: Duration = Duration::from_secs(2 * 60 * 60);
+
+/// The first and the longest interval between polls of the CI status.
+const CI_POLL_INTERVAL: Duration = Duration::from_secs(15);
+const CI_MAX_POLL_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// How long to back off after a secondary rate limit that doesn't say when
+/// to retry, as recommended by GitHub.
+const SECONDARY_RATE_LIMIT_DELAY: Duration = Duration::from_secs(60);
 
 #[derive(Debug)]
 pub enum Error {
     InvalidGitHubUrl(String, String),
     GitHubError(octocrab::Error),
-    TimedOut,
+    IoError(std::io::Error),
+    InvalidResponse(String, String),
+    /// GitHub asked us to slow down; the request can be retried after the
+    /// given delay.
+    RateLimited(Duration),
+    TimedOut(Duration),
+    /// No credentials were found for the host and prompting is disabled.
+    NoCredentials(String),
+    InvalidCredentials(String, String),
+    /// The token lacks all of the required scopes; the second field lists
+    /// the scopes it does have.
+    MissingScopes(Vec<String>, Vec<String>),
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::IoError(err)
+    }
 }
 
 impl From<octocrab::Error> for Error {
@@ -27,7 +55,32 @@ impl std::fmt::Display for Error {
                 write!(f, "Invalid GitHub URL {}: {}", url, msg)
             }
             Error::GitHubError(err) => write!(f, "GitHub Error: {}", err),
-            Error::TimedOut => write!(f, "Timed out waiting for GitHub"),
+            Error::IoError(err) => write!(f, "IO Error: {}", err),
+            Error::InvalidResponse(route, msg) => {
+                write!(f, "Invalid response from GitHub for {}: {}", route, msg)
+            }
+            Error::RateLimited(delay) => write!(
+                f,
+                "Rate limited by GitHub, retry in {} seconds",
+                delay.as_secs()
+            ),
+            Error::TimedOut(timeout) => write!(
+                f,
+                "Timed out after {} seconds waiting for GitHub",
+                timeout.as_secs()
+            ),
+            Error::NoCredentials(host) => {
+                write!(f, "No GitHub credentials found for {}", host)
+            }
+            Error::InvalidCredentials(host, msg) => {
+                write!(f, "Invalid GitHub credentials for {}: {}", host, msg)
+            }
+            Error::MissingScopes(required, granted) => write!(
+                f,
+                "GitHub token needs one of the scopes {}, but only has: {}",
+                required.join(", "),
+                granted.join(", ")
+            ),
         }
     }
 }
@@ -76,24 +129,8 @@ impl std::fmt::Display for GitHubCIStatus {
     }
 }
 
-pub fn init_github() -> Result<Octocrab, Error> {
-    let github_token = match std::env::var("GITHUB_TOKEN") {
-        Ok(token) => token,
-        Err(_) => {
-            debug!("GITHUB_TOKEN environment variable not set");
-            retrieve_github_token()
-        }
-    };
-
-    let instance = Octocrab::builder().personal_token(github_token).build()?;
-
-    Ok(instance)
-}
-
-pub async fn get_github_repo(
-    instance: &Octocrab,
-    repo_url: &url::Url,
-) -> Result<octocrab::models::Repository, Error> {
+/// Extract the owner and name of a repository from its URL.
+pub fn parse_repo_url(repo_url: &Url) -> Result<(String, String), Error> {
     // Remove ".git" from the end of the URL, if present
     let repo_url = repo_url.as_str();
     let repo_url = repo_url.strip_suffix(".git").unwrap_or(repo_url);
@@ -104,208 +141,1102 @@ pub async fn get_github_repo(
     let parsed_url = crate::drop_segment_parameters(&parsed_url);
 
     // Extract the owner and repo name from the URL
-    let path_segments: Vec<&str> = parsed_url.path_segments().unwrap().collect();
-    let owner = path_segments[0];
-    let repo_name = path_segments[1];
+    let path_segments: Vec<&str> = parsed_url
+        .path_segments()
+        .map(|segments| segments.filter(|s| !s.is_empty()).collect())
+        .unwrap_or_default();
+    match path_segments.as_slice() {
+        [owner, repo_name, ..] => Ok((owner.to_string(), repo_name.to_string())),
+        _ => Err(Error::InvalidGitHubUrl(
+            repo_url.to_string(),
+            "Missing owner or repository name".to_string(),
+        )),
+    }
+}
+
+pub async fn get_github_repo(
+    instance: &Octocrab,
+    repo_url: &url::Url,
+) -> Result<octocrab::models::Repository, Error> {
+    let (owner, repo_name) = parse_repo_url(repo_url)?;
 
-    // Retrieve the GitHub token using octocrab
     info!("Finding project {}/{} on GitHub", owner, repo_name);
 
     // Get the repository using octocrab
     Ok(instance.repos(owner, repo_name).get().await?)
 }
 
+/// The state of a single check run or commit status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CIState {
+    Success,
+    Pending,
+    Failure,
+}
+
+/// The result of a check run or a commit status context.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CIResult {
+    /// Name of the check run, or context of the commit status.
+    pub name: String,
+    pub state: CIState,
+    pub sha: String,
+    pub html_url: Option<String>,
+}
+
+/// Map the conclusion of a check run to a state.
+///
+/// `success` and `skipped` always pass; `passing` lists further conclusions,
+/// such as `neutral`, that should not block a release.
+fn check_run_state(conclusion: Option<&str>, passing: &[String]) -> CIState {
+    match conclusion {
+        None | Some("pending") => CIState::Pending,
+        Some("success") | Some("skipped") => CIState::Success,
+        Some(c) if passing.iter().any(|p| p == c) => CIState::Success,
+        Some(_) => CIState::Failure,
+    }
+}
+
+/// Map the state of a commit status to a state.
+fn commit_status_state(state: &str) -> CIState {
+    match state {
+        "success" => CIState::Success,
+        "pending" => CIState::Pending,
+        _ => CIState::Failure,
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct CommitStatus {
+    context: String,
+    state: String,
+    target_url: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+struct CombinedStatus {
+    total_count: u64,
+    statuses: Vec<CommitStatus>,
+}
+
+#[derive(serde::Deserialize)]
+struct RequiredStatusCheck {
+    context: String,
+}
+
+#[derive(serde::Deserialize)]
+struct RequiredStatusChecks {
+    #[serde(default)]
+    contexts: Vec<String>,
+    #[serde(default)]
+    checks: Vec<RequiredStatusCheck>,
+}
+
+const CI_PAGE_SIZE: u8 = 100;
+
+/// Determine how long to wait before retrying a rate-limited request, from
+/// the status and headers of the response.
+///
+/// `now` is the current time in seconds since the epoch, used to interpret
+/// `x-ratelimit-reset`. Returns `None` if the response is not rate limited.
+pub fn rate_limit_delay(
+    status: u16,
+    retry_after: Option<&str>,
+    remaining: Option<&str>,
+    reset: Option<&str>,
+    now: u64,
+) -> Option<Duration> {
+    if status != 403 && status != 429 {
+        return None;
+    }
+    if let Some(retry_after) = retry_after.and_then(|r| r.trim().parse::<u64>().ok()) {
+        return Some(Duration::from_secs(retry_after));
+    }
+    if remaining.map(|r| r.trim()) == Some("0") {
+        if let Some(reset) = reset.and_then(|r| r.trim().parse::<u64>().ok()) {
+            return Some(Duration::from_secs(reset.saturating_sub(now).max(1)));
+        }
+    }
+    if status == 429 {
+        return Some(SECONDARY_RATE_LIMIT_DELAY);
+    }
+    None
+}
+
+/// Fetch a JSON document from the GitHub API, reporting rate limiting as
+/// [`Error::RateLimited`].
+async fn get_json<R: serde::de::DeserializeOwned>(
+    instance: &Octocrab,
+    route: &str,
+) -> Result<R, Error> {
+    let response = instance._get(route).await?;
+    let header = |name: &str| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string())
+    };
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    if let Some(delay) = rate_limit_delay(
+        response.status().as_u16(),
+        header("retry-after").as_deref(),
+        header("x-ratelimit-remaining").as_deref(),
+        header("x-ratelimit-reset").as_deref(),
+        now,
+    ) {
+        return Err(Error::RateLimited(delay));
+    }
+    let response = octocrab::map_github_error(response).await?;
+    let body = instance.body_to_string(response).await?;
+    serde_json::from_str(&body)
+        .map_err(|e| Error::InvalidResponse(route.to_string(), e.to_string()))
+}
+
+/// Collect the check runs and commit statuses for a commit.
+async fn ci_results(
+    instance: &Octocrab,
+    repo: &octocrab::models::Repository,
+    sha: &str,
+    ci: &crate::project_config::CIConfig,
+) -> Result<Vec<CIResult>, Error> {
+    let owner = &repo.owner.as_ref().unwrap().login;
+    let mut results = vec![];
+
+    for page in 1u32.. {
+        let check_runs: octocrab::models::checks::ListCheckRuns = get_json(
+            instance,
+            &format!(
+                "/repos/{}/{}/commits/{}/check-runs?per_page={}&page={}",
+                owner, repo.name, sha, CI_PAGE_SIZE, page
+            ),
+        )
+        .await?;
+        let done = check_runs.check_runs.is_empty();
+        results.extend(check_runs.check_runs.into_iter().map(|check| CIResult {
+            state: check_run_state(check.conclusion.as_deref(), &ci.passing_conclusions),
+            name: check.name,
+            sha: check.head_sha,
+            html_url: check.html_url,
+        }));
+        if done || results.len() as u64 >= check_runs.total_count {
+            break;
+        }
+    }
+
+    let mut seen = 0;
+    for page in 1u32.. {
+        let combined: CombinedStatus = get_json(
+            instance,
+            &format!(
+                "/repos/{}/{}/commits/{}/status?per_page={}&page={}",
+                owner, repo.name, sha, CI_PAGE_SIZE, page
+            ),
+        )
+        .await?;
+        seen += combined.statuses.len() as u64;
+        let done = combined.statuses.is_empty();
+        results.extend(combined.statuses.into_iter().map(|status| CIResult {
+            name: status.context,
+            state: commit_status_state(&status.state),
+            sha: sha.to_string(),
+            html_url: status.target_url,
+        }));
+        if done || seen >= combined.total_count {
+            break;
+        }
+    }
+
+    Ok(results)
+}
+
+/// Find the checks that branch protection requires to pass on a branch.
+///
+/// Returns `None` if the branch is not protected, or if the protection
+/// settings can't be read with the current credentials.
+async fn required_checks(
+    instance: &Octocrab,
+    repo: &octocrab::models::Repository,
+    branch: &str,
+) -> Result<Option<Vec<String>>, Error> {
+    let route = format!(
+        "/repos/{}/{}/branches/{}/protection/required_status_checks",
+        repo.owner.as_ref().unwrap().login,
+        repo.name,
+        branch
+    );
+    match get_json::<RequiredStatusChecks>(instance, &route).await {
+        Ok(required) => {
+            let mut names = required.contexts;
+            for check in required.checks {
+                if !names.contains(&check.context) {
+                    names.push(check.context);
+                }
+            }
+            Ok(Some(names))
+        }
+        Err(Error::GitHubError(octocrab::Error::GitHub { source, .. }))
+            if [403, 404].contains(&source.status_code.as_u16()) =>
+        {
+            log::warn!(
+                "Unable to read required checks for {}: {}; considering all checks",
+                branch,
+                source.message
+            );
+            Ok(None)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct CommitRef {
+    sha: String,
+}
+
+/// Resolve a committish to the SHA of a commit.
+async fn commit_sha(
+    instance: &Octocrab,
+    repo: &octocrab::models::Repository,
+    committish: &str,
+) -> Result<String, Error> {
+    let route = format!(
+        "/repos/{}/{}/commits/{}",
+        repo.owner.as_ref().unwrap().login,
+        repo.name,
+        committish
+    );
+    Ok(get_json::<CommitRef>(instance, &route).await?.sha)
+}
+
+/// Combine the results of the individual checks.
+///
+/// If `required` is set, only those checks are considered, and a required
+/// check that hasn't reported yet is pending.
+pub fn summarize_results(
+    sha: &str,
+    results: &[CIResult],
+    required: Option<&[String]>,
+) -> GitHubCIStatus {
+    let relevant = results
+        .iter()
+        .filter(|r| required.is_none_or(|req| req.contains(&r.name)))
+        .collect::<Vec<_>>();
+
+    if let Some(failed) = relevant.iter().find(|r| r.state == CIState::Failure) {
+        error!(
+            "GitHub Status Failed ({}): SHA {}, URL {}",
+            failed.name,
+            failed.sha,
+            failed.html_url.as_deref().unwrap_or("None")
+        );
+        return GitHubCIStatus::Failed {
+            sha: failed.sha.clone(),
+            html_url: failed.html_url.clone(),
+        };
+    }
+
+    if let Some(pending) = relevant.iter().find(|r| r.state == CIState::Pending) {
+        error!(
+            "GitHub Status Pending ({}): SHA {}, URL {}",
+            pending.name,
+            pending.sha,
+            pending.html_url.as_deref().unwrap_or("None")
+        );
+        return GitHubCIStatus::Pending {
+            sha: pending.sha.clone(),
+            html_url: pending.html_url.clone(),
+        };
+    }
+
+    if let Some(missing) = required
+        .unwrap_or_default()
+        .iter()
+        .find(|name| !relevant.iter().any(|r| &&r.name == name))
+    {
+        error!(
+            "GitHub Status Pending: required check {} has not reported for SHA {}",
+            missing, sha
+        );
+        return GitHubCIStatus::Pending {
+            sha: sha.to_string(),
+            html_url: None,
+        };
+    }
+
+    GitHubCIStatus::Ok
+}
+
+/// Find the required checks to restrict to, if configured.
+async fn ci_required(
+    instance: &Octocrab,
+    repo: &octocrab::models::Repository,
+    branch: Option<&str>,
+    ci: &crate::project_config::CIConfig,
+) -> Result<Option<Vec<String>>, Error> {
+    if !ci.required_only {
+        return Ok(None);
+    }
+    match branch.or(repo.default_branch.as_deref()) {
+        Some(branch) => required_checks(instance, repo, branch).await,
+        None => Ok(None),
+    }
+}
+
+/// Check the CI status of a commit, combining check runs and commit statuses.
+///
+/// `branch` is the branch whose required checks are used when
+/// `ci.required_only` is set; it defaults to the default branch.
 pub async fn check_gh_repo_action_status(
     instance: &Octocrab,
     repo: &octocrab::models::Repository,
     committish: Option<&str>,
+    branch: Option<&str>,
+    ci: &crate::project_config::CIConfig,
 ) -> Result<GitHubCIStatus, Error> {
     let committish = committish.unwrap_or("HEAD");
 
-    let commit = instance
-        .commits(&repo.owner.as_ref().unwrap().login, &repo.name)
-        .get(committish)
-        .await?;
+    let sha = commit_sha(instance, repo, committish).await?;
+    let required = ci_required(instance, repo, branch, ci).await?;
+    let results = ci_results(instance, repo, &sha, ci).await?;
+    Ok(summarize_results(&sha, &results, required.as_deref()))
+}
 
-    for check in instance
-        .checks(&repo.owner.as_ref().unwrap().login, &repo.name)
-        .list_check_runs_for_git_ref(Commitish(commit.sha.clone()))
-        .send()
-        .await?
-        .check_runs
-    {
-        match check.conclusion.as_deref() {
-            Some("success") | Some("skipped") => continue,
-            Some(_) => {
-                error!(
-                    "GitHub Status Failed: SHA {}, URL {}",
-                    check.head_sha,
-                    check.html_url.as_ref().unwrap_or(&"None".to_string())
-                );
-                return Ok(GitHubCIStatus::Failed {
-                    sha: check.head_sha,
-                    html_url: check.html_url,
-                });
-            }
-            None => {
-                error!(
-                    "GitHub Status Pending: SHA {}, URL {}",
-                    check.head_sha,
-                    check.html_url.as_ref().unwrap_or(&"None".to_string())
+/// Names of the checks that have not finished yet.
+pub fn pending_checks(results: &[CIResult], required: Option<&[String]>) -> Vec<String> {
+    let mut pending = results
+        .iter()
+        .filter(|r| r.state == CIState::Pending)
+        .filter(|r| required.is_none_or(|req| req.contains(&r.name)))
+        .map(|r| r.name.clone())
+        .collect::<Vec<_>>();
+    for name in required.unwrap_or_default() {
+        if !results.iter().any(|r| &r.name == name) && !pending.contains(name) {
+            pending.push(name.clone());
+        }
+    }
+    pending
+}
+
+/// Run a request, waiting and retrying for as long as GitHub rate limits us.
+///
+/// Gives up with [`Error::TimedOut`] once `deadline` has passed.
+async fn retry_rate_limited<T, F, Fut>(
+    deadline: std::time::Instant,
+    timeout: Duration,
+    mut request: F,
+) -> Result<T, Error>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<T, Error>>,
+{
+    loop {
+        match request().await {
+            Err(Error::RateLimited(delay)) => {
+                log::warn!(
+                    "Rate limited by GitHub; waiting {} seconds",
+                    delay.as_secs()
                 );
-                return Ok(GitHubCIStatus::Pending {
-                    sha: check.head_sha,
-                    html_url: check.html_url.clone(),
-                });
+                let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+                if remaining.is_zero() {
+                    return Err(Error::TimedOut(timeout));
+                }
+                tokio::time::sleep(delay.min(remaining)).await;
             }
+            result => return result,
         }
     }
-
-    Ok(GitHubCIStatus::Ok)
 }
 
+/// Wait for CI on a commit to finish.
+///
+/// The status is polled with exponential backoff, and polling pauses while
+/// GitHub rate limits us. Gives up with [`Error::TimedOut`] after `timeout`,
+/// which defaults to two hours.
 pub async fn wait_for_gh_actions(
     instance: &Octocrab,
     repo: &octocrab::models::Repository,
     committish: Option<&str>,
-    timeout: Option<u64>,
+    branch: Option<&str>,
+    ci: &crate::project_config::CIConfig,
+    timeout: Option<Duration>,
 ) -> Result<GitHubCIStatus, Error> {
     let timeout = timeout.unwrap_or(DEFAULT_GITHUB_CI_TIMEOUT);
     info!(
-        "Waiting for CI for {} on {} to go green",
+        "Waiting up to {} seconds for CI for {} on {} to go green",
+        timeout.as_secs(),
         repo.name,
         committish.unwrap_or("HEAD")
     );
     let committish = committish.unwrap_or("HEAD");
+    let deadline = std::time::Instant::now() + timeout;
 
-    let commit = instance
-        .commits(&repo.owner.as_ref().unwrap().login, &repo.name)
-        .get(committish)
-        .await?;
-
-    let start_time = std::time::Instant::now();
+    let sha =
+        retry_rate_limited(deadline, timeout, || commit_sha(instance, repo, committish)).await?;
+    let required = retry_rate_limited(deadline, timeout, || {
+        ci_required(instance, repo, branch, ci)
+    })
+    .await?;
 
-    while start_time.elapsed().as_secs() < timeout {
-        let check_runs = instance
-            .checks(&repo.owner.as_ref().unwrap().login, &repo.name)
-            .list_check_runs_for_git_ref(Commitish(commit.sha.clone()))
-            .send()
-            .await?
-            .check_runs;
+    let mut interval = CI_POLL_INTERVAL;
 
-        match summarize_status(check_runs.as_slice()) {
-            GitHubCIStatus::Ok => {
-                info!("CI for {} on {} is green", repo.name, committish);
-                return Ok(GitHubCIStatus::Ok);
-            }
-            GitHubCIStatus::Pending { .. } => {
-                std::thread::sleep(Duration::from_secs(30));
-            }
-            GitHubCIStatus::Failed { html_url, sha } => {
-                return Ok(GitHubCIStatus::Failed { sha, html_url });
+    loop {
+        let delay = match ci_results(instance, repo, &sha, ci).await {
+            Ok(results) => match summarize_results(&sha, &results, required.as_deref()) {
+                GitHubCIStatus::Ok => {
+                    info!("CI for {} on {} is green", repo.name, committish);
+                    return Ok(GitHubCIStatus::Ok);
+                }
+                GitHubCIStatus::Failed { html_url, sha } => {
+                    return Ok(GitHubCIStatus::Failed { sha, html_url });
+                }
+                GitHubCIStatus::Pending { .. } => {
+                    info!(
+                        "Still waiting for: {}",
+                        pending_checks(&results, required.as_deref()).join(", ")
+                    );
+                    let delay = interval;
+                    interval = (interval * 2).min(CI_MAX_POLL_INTERVAL);
+                    delay
+                }
+            },
+            Err(Error::RateLimited(delay)) => {
+                log::warn!(
+                    "Rate limited by GitHub; waiting {} seconds",
+                    delay.as_secs()
+                );
+                delay
             }
+            Err(e) => return Err(e),
+        };
+
+        let remaining = deadline.saturating_duration_since(std::time::Instant::now());
+        if remaining.is_zero() {
+            return Err(Error::TimedOut(timeout));
         }
+        tokio::time::sleep(delay.min(remaining)).await;
     }
-
-    Err(Error::TimedOut)
 }
 
-fn summarize_status(check_runs: &[octocrab::models::checks::CheckRun]) -> GitHubCIStatus {
-    for check in check_runs {
-        match check.conclusion.as_deref() {
-            Some("success") | Some("skipped") => {}
-            Some("pending") => {
-                error!(
-                    "GitHub Status Pending: SHA {}, URL {}",
-                    check.head_sha,
-                    check.html_url.as_ref().unwrap_or(&"None".to_string())
-                );
-                return GitHubCIStatus::Pending {
-                    sha: check.head_sha.clone(),
-                    html_url: check.html_url.clone(),
-                };
-            }
-            Some(e) => {
-                error!(
-                    "GitHub Status Failed ({}): SHA {}, URL {}",
-                    e,
-                    check.head_sha,
-                    check.html_url.as_ref().unwrap_or(&"None".to_string())
-                );
-                return GitHubCIStatus::Failed {
-                    sha: check.head_sha.clone(),
-                    html_url: check.html_url.clone(),
-                };
-            }
-            None => {
-                error!(
-                    "GitHub Status Pending: SHA {}, URL {}",
-                    check.head_sha,
-                    check.html_url.as_ref().unwrap_or(&"None".to_string())
-                );
-                return GitHubCIStatus::Pending {
-                    sha: check.head_sha.clone(),
-                    html_url: check.html_url.clone(),
-                };
-            }
-        }
-    }
+/// Find the release for a tag, including draft releases.
+async fn find_release_by_tag(
+    instance: &Octocrab,
+    repo: &octocrab::models::Repository,
+    tag_name: &str,
+) -> Result<Option<octocrab::models::repos::Release>, Error> {
+    let page = instance
+        .repos(&repo.owner.as_ref().unwrap().login, &repo.name)
+        .releases()
+        .list()
+        .per_page(100)
+        .send()
+        .await?;
+    Ok(instance
+        .all_pages(page)
+        .await?
+        .into_iter()
+        .find(|r| r.tag_name == tag_name))
+}
 
-    GitHubCIStatus::Ok
+/// Whether a version is a pre-release, such as "1.0rc1", "1.0.0-beta.2"
+/// or "2.0.dev1".
+pub fn is_prerelease(version: &str) -> bool {
+    lazy_regex::regex_is_match!(
+        r"(?i)^v?[0-9]+(\.[0-9]+)*[-_.+]?(a|b|c|rc|alpha|beta|pre|preview|dev)[-_.]?[0-9]*([-+.].*)?$",
+        version
+    )
 }
 
+/// Create the GitHub release for a tag, or update the release if one already
+/// exists, e.g. from an earlier attempt or drafted by hand.
+///
+/// The release is left as a draft if `options.draft` is set or the existing
+/// release is a draft; see [`publish_github_release`].
 pub async fn create_github_release(
     instance: &Octocrab,
     repo: &octocrab::models::Repository,
     tag_name: &str,
     version: &str,
     description: Option<&str>,
-) -> Result<(), Error> {
+    options: &crate::project_config::GitHubRelease,
+) -> Result<octocrab::models::repos::Release, Error> {
+    let body = description
+        .map(|d| d.to_string())
+        .unwrap_or_else(|| format!("Release {}.", version));
+    let repos = instance.repos(&repo.owner.as_ref().unwrap().login, &repo.name);
+    let releases = repos.releases();
+    let prerelease = options.prerelease || is_prerelease(version);
+
+    if let Some(existing) = find_release_by_tag(instance, repo, tag_name).await? {
+        info!("Updating existing release for {} on GitHub", tag_name);
+        return Ok(releases
+            .update(existing.id.into_inner())
+            .name(version)
+            .body(&body)
+            .draft(options.draft || existing.draft)
+            .prerelease(prerelease || existing.prerelease)
+            .send()
+            .await?);
+    }
+
     info!("Creating release on GitHub");
 
+    Ok(releases
+        .create(tag_name)
+        .name(version)
+        .body(&body)
+        .draft(options.draft)
+        .prerelease(prerelease)
+        .send()
+        .await?)
+}
+
+/// Publish a release that is still a draft.
+pub async fn publish_github_release(
+    instance: &Octocrab,
+    repo: &octocrab::models::Repository,
+    release: &octocrab::models::repos::Release,
+) -> Result<(), Error> {
+    if !release.draft {
+        return Ok(());
+    }
+    info!("Publishing draft release {}", release.tag_name);
     instance
         .repos(&repo.owner.as_ref().unwrap().login, &repo.name)
         .releases()
-        .create(tag_name)
-        .name(version)
-        .body(description.unwrap_or(&format!("Release {}.", version)))
+        .update(release.id.into_inner())
+        .draft(false)
         .send()
         .await?;
-
     Ok(())
 }
 
-pub fn login() -> Result<Octocrab, Error> {
-    let entry = keyring::Entry::new("github.com", "personal_token").unwrap();
-    let token = match std::env::var("GITHUB_TOKEN") {
-        Ok(token) => Some(token),
-        Err(std::env::VarError::NotPresent) => match entry.get_password() {
-            Ok(token) => Some(token),
-            Err(keyring::Error::NoEntry) => None,
-            Err(e) => {
-                log::error!("Unable to read GitHub personal token from keyring: {}", e);
-                None
+/// Extensions of signature and checksum files that are attached to a release
+/// along with the artifact they belong to.
+const ASSET_SIDECAR_EXTENSIONS: &[&str] = &["asc", "sig", "sha256", "sha512"];
+
+/// Find the files to attach to a release for a set of artifacts.
+///
+/// This includes signature and checksum files next to the artifacts, such as
+/// `foo-1.0.tar.gz.asc`. Duplicates are removed.
+pub fn release_asset_paths(artifacts: &[PathBuf]) -> Vec<PathBuf> {
+    let mut ret: Vec<PathBuf> = vec![];
+    for artifact in artifacts {
+        let sidecars = ASSET_SIDECAR_EXTENSIONS.iter().map(|ext| {
+            let mut path = artifact.clone().into_os_string();
+            path.push(".");
+            path.push(ext);
+            PathBuf::from(path)
+        });
+        for path in std::iter::once(artifact.clone()).chain(sidecars.filter(|p| p.exists())) {
+            if !ret.contains(&path) {
+                ret.push(path);
             }
-        },
-        Err(e) => {
-            log::error!(
+        }
+    }
+    ret
+}
+
+/// Upload files as assets of a release.
+///
+/// Existing assets with the same name, e.g. from an earlier attempt at the
+/// release, are replaced.
+pub async fn upload_release_assets(
+    instance: &Octocrab,
+    repo: &octocrab::models::Repository,
+    release: &octocrab::models::repos::Release,
+    paths: &[PathBuf],
+) -> Result<(), Error> {
+    let repos = instance.repos(&repo.owner.as_ref().unwrap().login, &repo.name);
+    for path in paths {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    format!("{} is not a file", path.display()),
+                )
+            })?;
+        if let Some(existing) = release.assets.iter().find(|a| a.name == name) {
+            info!("Replacing release asset {}", name);
+            repos
+                .release_assets()
+                .delete(existing.id.into_inner())
+                .await?;
+        } else {
+            info!("Uploading release asset {}", name);
+        }
+        let body = std::fs::read(Path::new(path))?;
+        repos
+            .releases()
+            .upload_asset(release.id.into_inner(), &name, bytes::Bytes::from(body))
+            .send()
+            .await?;
+    }
+    Ok(())
+}
+
+/// The host name of the public GitHub instance.
+pub const GITHUB_COM: &str = "github.com";
+
+/// Whether `host` is github.com or one of the configured GitHub Enterprise
+/// Server hosts.
+pub fn is_github_host(host: &str, hosts: Option<&HashMap<String, GitHubHostConfig>>) -> bool {
+    host == GITHUB_COM || hosts.is_some_and(|hosts| hosts.contains_key(host))
+}
+
+/// The REST API endpoint of a GitHub Enterprise Server host.
+pub fn enterprise_api_url(host: &str, config: &GitHubHostConfig) -> Result<Url, Error> {
+    match config.api_url.as_ref() {
+        Some(url) => Ok(url.clone()),
+        None => Url::parse(&format!("https://{}/api/v3/", host))
+            .map_err(|e| Error::InvalidGitHubUrl(host.to_string(), e.to_string())),
+    }
+}
+
+/// A way of authenticating to a GitHub instance.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Credential {
+    /// A classic or fine-grained personal access token.
+    Token(String),
+    /// A GitHub App, which mints installation tokens as needed.
+    App {
+        app_id: u64,
+        private_key: Vec<u8>,
+        installation_id: Option<u64>,
+    },
+}
+
+/// Find credentials for `host` without prompting.
+///
+/// The sources are tried in order: `GITHUB_TOKEN` (github.com only), the
+/// keyring, the token in `host_config` or its `token-file`, and finally its
+/// GitHub App settings. Returns the credential and a description of where
+/// it came from.
+pub fn find_credential(
+    host: &str,
+    host_config: Option<&GitHubHostConfig>,
+) -> Result<Option<(Credential, String)>, Error> {
+    if host == GITHUB_COM {
+        match std::env::var("GITHUB_TOKEN") {
+            Ok(token) => return Ok(Some((Credential::Token(token), "GITHUB_TOKEN".to_string()))),
+            Err(std::env::VarError::NotPresent) => {}
+            Err(e) => log::error!(
                 "Unable to read GitHub personal token from environment: {}",
                 e
-            );
-            None
+            ),
         }
+    }
+    match keyring::Entry::new(host, "personal_token").and_then(|entry| entry.get_password()) {
+        Ok(token) => return Ok(Some((Credential::Token(token), "keyring".to_string()))),
+        Err(keyring::Error::NoEntry) => {}
+        Err(e) => log::error!("Unable to read GitHub personal token from keyring: {}", e),
+    }
+    if let Some(config) = host_config {
+        if let Some(token) = config.token.as_ref() {
+            return Ok(Some((
+                Credential::Token(token.clone()),
+                "configuration".to_string(),
+            )));
+        }
+        if let Some(path) = config.token_file.as_ref() {
+            let token = std::fs::read_to_string(path)?.trim().to_string();
+            return Ok(Some((Credential::Token(token), path.display().to_string())));
+        }
+        match (config.app_id, config.private_key_file.as_ref()) {
+            (Some(app_id), Some(path)) => {
+                return Ok(Some((
+                    Credential::App {
+                        app_id,
+                        private_key: std::fs::read(path)?,
+                        installation_id: config.installation_id,
+                    },
+                    format!("GitHub App {}", app_id),
+                )));
+            }
+            (Some(_), None) | (None, Some(_)) => {
+                log::warn!(
+                    "Both app-id and private-key-file are needed to authenticate to {} as a GitHub App",
+                    host
+                );
+            }
+            (None, None) => {}
+        }
+    }
+    Ok(None)
+}
+
+/// Log in to the GitHub instance hosting `repo_url`.
+///
+/// If no credentials can be found the user is asked for a personal token,
+/// which is stored in the keyring; unless `interactive` is false or standard
+/// input is not a terminal, in which case [`Error::NoCredentials`] is
+/// returned.
+pub async fn login(
+    repo_url: &Url,
+    host_config: Option<&GitHubHostConfig>,
+    interactive: bool,
+) -> Result<Octocrab, Error> {
+    let host = repo_url.host_str().unwrap_or(GITHUB_COM);
+    let credential = match find_credential(host, host_config)? {
+        Some((credential, source)) => {
+            log::info!("Using GitHub credentials for {} from {}", host, source);
+            credential
+        }
+        None if interactive && std::io::stdin().is_terminal() => {
+            println!("Please enter your GitHub personal token for {}", host);
+            let mut personal_token = String::new();
+            std::io::stdin().read_line(&mut personal_token)?;
+            let personal_token = personal_token.trim().to_string();
+            if let Err(e) = keyring::Entry::new(host, "personal_token")
+                .and_then(|entry| entry.set_password(&personal_token))
+            {
+                log::warn!("Unable to store GitHub personal token in keyring: {}", e);
+            }
+            Credential::Token(personal_token)
+        }
+        None => return Err(Error::NoCredentials(host.to_string())),
     };
 
-    let builder = if let Some(token) = token {
-        log::info!("Using GitHub personal token from keyring");
-        octocrab::OctocrabBuilder::new().personal_token(token)
-    } else {
-        println!("Please enter your GitHub personal token");
-        let mut personal_token = String::new();
-        std::io::stdin().read_line(&mut personal_token).unwrap();
-        let personal_token = personal_token.trim();
-        entry.set_password(personal_token).unwrap();
-        octocrab::OctocrabBuilder::new().personal_token(personal_token.to_string())
+    let mut builder = octocrab::OctocrabBuilder::new();
+    if host != GITHUB_COM {
+        // Release assets are uploaded to the URL given in the release, which
+        // is on the same host; the upload URI only decides where the token
+        // may be sent.
+        let api_url = enterprise_api_url(host, host_config.unwrap_or(&Default::default()))?;
+        builder = builder
+            .base_uri(api_url.as_str())?
+            .upload_uri(api_url.as_str())?;
+    }
+    let instance = match credential {
+        Credential::Token(token) => builder.personal_token(token).build()?,
+        Credential::App {
+            app_id,
+            private_key,
+            installation_id,
+        } => {
+            let key = jsonwebtoken::EncodingKey::from_rsa_pem(&private_key).map_err(|e| {
+                Error::InvalidCredentials(host.to_string(), format!("invalid private key: {}", e))
+            })?;
+            let app = builder.app(app_id.into(), key).build()?;
+            let installation_id = match installation_id {
+                Some(id) => id.into(),
+                None => {
+                    let (owner, repo_name) = parse_repo_url(repo_url)?;
+                    app.apps()
+                        .get_repository_installation(owner, repo_name)
+                        .await?
+                        .id
+                }
+            };
+            app.installation(installation_id)?
+        }
     };
-    Ok(builder.build()?)
+    verify_token(&instance, host).await?;
+    Ok(instance)
+}
+
+/// Scopes of a classic personal token, any of which allows creating
+/// releases and reading CI results.
+const REQUIRED_SCOPES: &[&str] = &["repo", "public_repo"];
+
+/// Check the scopes reported in the `X-OAuth-Scopes` header of a response.
+///
+/// Fine-grained tokens and installation tokens don't report scopes; their
+/// permissions are only checked when they are used.
+pub fn check_token_scopes(scopes: Option<&str>) -> Result<(), Error> {
+    let Some(scopes) = scopes else {
+        return Ok(());
+    };
+    let granted = scopes
+        .split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>();
+    if granted.iter().any(|s| REQUIRED_SCOPES.contains(s)) {
+        Ok(())
+    } else {
+        Err(Error::MissingScopes(
+            REQUIRED_SCOPES.iter().map(|s| s.to_string()).collect(),
+            granted.iter().map(|s| s.to_string()).collect(),
+        ))
+    }
+}
+
+/// Check that the credentials of `instance` are accepted and have the
+/// scopes needed for releasing.
+async fn verify_token(instance: &Octocrab, host: &str) -> Result<(), Error> {
+    let response = instance._get("/rate_limit").await?;
+    if response.status().as_u16() == 401 {
+        return Err(Error::InvalidCredentials(
+            host.to_string(),
+            "rejected by GitHub".to_string(),
+        ));
+    }
+    let scopes = response
+        .headers()
+        .get("x-oauth-scopes")
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.to_string());
+    octocrab::map_github_error(response).await?;
+    check_token_scopes(scopes.as_deref())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(name: &str, state: CIState) -> CIResult {
+        CIResult {
+            name: name.to_string(),
+            state,
+            sha: "abc".to_string(),
+            html_url: None,
+        }
+    }
+
+    #[test]
+    fn test_check_run_state() {
+        assert_eq!(check_run_state(Some("success"), &[]), CIState::Success);
+        assert_eq!(check_run_state(Some("skipped"), &[]), CIState::Success);
+        assert_eq!(check_run_state(None, &[]), CIState::Pending);
+        assert_eq!(check_run_state(Some("neutral"), &[]), CIState::Failure);
+        assert_eq!(
+            check_run_state(Some("neutral"), &["neutral".to_string()]),
+            CIState::Success
+        );
+        assert_eq!(check_run_state(Some("failure"), &[]), CIState::Failure);
+        assert_eq!(commit_status_state("error"), CIState::Failure);
+        assert_eq!(commit_status_state("pending"), CIState::Pending);
+    }
+
+    #[test]
+    fn test_rate_limit_delay() {
+        assert_eq!(rate_limit_delay(200, Some("10"), None, None, 0), None);
+        assert_eq!(
+            rate_limit_delay(403, Some("10"), None, None, 0),
+            Some(Duration::from_secs(10))
+        );
+        assert_eq!(
+            rate_limit_delay(403, None, Some("0"), Some("1060"), 1000),
+            Some(Duration::from_secs(60))
+        );
+        assert_eq!(
+            rate_limit_delay(403, None, Some("0"), Some("900"), 1000),
+            Some(Duration::from_secs(1))
+        );
+        assert_eq!(
+            rate_limit_delay(403, None, Some("10"), Some("1060"), 1000),
+            None
+        );
+        assert_eq!(
+            rate_limit_delay(429, None, None, None, 0),
+            Some(SECONDARY_RATE_LIMIT_DELAY)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_retry_rate_limited() {
+        let timeout = Duration::from_secs(60);
+        let deadline = std::time::Instant::now() + timeout;
+        let mut attempts = 0;
+        let result = retry_rate_limited(deadline, timeout, || {
+            attempts += 1;
+            let attempt = attempts;
+            async move {
+                if attempt < 3 {
+                    Err(Error::RateLimited(Duration::ZERO))
+                } else {
+                    Ok(attempt)
+                }
+            }
+        })
+        .await;
+        assert_eq!(result.unwrap(), 3);
+
+        let result = retry_rate_limited(std::time::Instant::now(), timeout, || async {
+            Err::<(), _>(Error::RateLimited(Duration::ZERO))
+        })
+        .await;
+        assert!(matches!(result, Err(Error::TimedOut(_))));
+    }
+
+    #[test]
+    fn test_pending_checks() {
+        let results = vec![
+            result("build", CIState::Success),
+            result("docs", CIState::Pending),
+            result("lint", CIState::Pending),
+        ];
+        assert_eq!(pending_checks(&results, None), vec!["docs", "lint"]);
+        assert_eq!(
+            pending_checks(
+                &results,
+                Some(&["docs".to_string(), "coverage".to_string()])
+            ),
+            vec!["docs", "coverage"]
+        );
+    }
+
+    #[test]
+    fn test_summarize_results() {
+        let results = vec![
+            result("build", CIState::Success),
+            result("docs", CIState::Pending),
+            result("lint", CIState::Failure),
+        ];
+        assert!(matches!(
+            summarize_results("abc", &results, None),
+            GitHubCIStatus::Failed { .. }
+        ));
+        assert!(matches!(
+            summarize_results("abc", &results[..2], None),
+            GitHubCIStatus::Pending { .. }
+        ));
+        assert!(summarize_results("abc", &results, Some(&["build".to_string()])).is_ok());
+        assert!(matches!(
+            summarize_results(
+                "abc",
+                &results,
+                Some(&["build".to_string(), "coverage".to_string()])
+            ),
+            GitHubCIStatus::Pending { .. }
+        ));
+        assert!(summarize_results("abc", &[], None).is_ok());
+    }
+
+    #[test]
+    fn test_release_asset_paths() {
+        let td = tempfile::tempdir().unwrap();
+        let sdist = td.path().join("foo-1.0.tar.gz");
+        let wheel = td.path().join("foo-1.0-py3-none-any.whl");
+        std::fs::write(&sdist, "").unwrap();
+        std::fs::write(&wheel, "").unwrap();
+        std::fs::write(td.path().join("foo-1.0.tar.gz.asc"), "").unwrap();
+        std::fs::write(td.path().join("foo-1.0.tar.gz.sha256"), "").unwrap();
+
+        assert_eq!(
+            release_asset_paths(&[sdist.clone(), wheel.clone(), sdist.clone()]),
+            vec![
+                sdist.clone(),
+                td.path().join("foo-1.0.tar.gz.asc"),
+                td.path().join("foo-1.0.tar.gz.sha256"),
+                wheel,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_is_github_host() {
+        let hosts = HashMap::from([(
+            "github.example.com".to_string(),
+            GitHubHostConfig::default(),
+        )]);
+        assert!(is_github_host("github.com", None));
+        assert!(!is_github_host("github.example.com", None));
+        assert!(is_github_host("github.example.com", Some(&hosts)));
+        assert!(!is_github_host("gitlab.com", Some(&hosts)));
+    }
+
+    #[test]
+    fn test_enterprise_api_url() {
+        assert_eq!(
+            enterprise_api_url("github.example.com", &GitHubHostConfig::default())
+                .unwrap()
+                .as_str(),
+            "https://github.example.com/api/v3/"
+        );
+        let config = GitHubHostConfig {
+            api_url: Some("https://api.github.example.com/".parse().unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(
+            enterprise_api_url("github.example.com", &config)
+                .unwrap()
+                .as_str(),
+            "https://api.github.example.com/"
+        );
+    }
+
+    #[test]
+    fn test_find_credential() {
+        let td = tempfile::tempdir().unwrap();
+        let token_file = td.path().join("token");
+        std::fs::write(&token_file, "ghp_file\n").unwrap();
+
+        let config = GitHubHostConfig {
+            token: Some("ghp_config".to_string()),
+            token_file: Some(token_file.clone()),
+            ..Default::default()
+        };
+        assert_eq!(
+            find_credential("github.example.com", Some(&config))
+                .unwrap()
+                .unwrap()
+                .0,
+            Credential::Token("ghp_config".to_string())
+        );
+
+        let config = GitHubHostConfig {
+            token_file: Some(token_file),
+            app_id: Some(1),
+            private_key_file: Some(td.path().join("app.pem")),
+            ..Default::default()
+        };
+        assert_eq!(
+            find_credential("github.example.com", Some(&config))
+                .unwrap()
+                .unwrap()
+                .0,
+            Credential::Token("ghp_file".to_string())
+        );
+
+        std::fs::write(td.path().join("app.pem"), "key").unwrap();
+        let config = GitHubHostConfig {
+            app_id: Some(1),
+            private_key_file: Some(td.path().join("app.pem")),
+            installation_id: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(
+            find_credential("github.example.com", Some(&config))
+                .unwrap()
+                .unwrap()
+                .0,
+            Credential::App {
+                app_id: 1,
+                private_key: b"key".to_vec(),
+                installation_id: Some(2),
+            }
+        );
+    }
+
+    #[test]
+    fn test_check_token_scopes() {
+        assert!(check_token_scopes(None).is_ok());
+        assert!(check_token_scopes(Some("repo, workflow")).is_ok());
+        assert!(check_token_scopes(Some("public_repo")).is_ok());
+        match check_token_scopes(Some("read:org, gist")) {
+            Err(Error::MissingScopes(_, granted)) => {
+                assert_eq!(granted, vec!["read:org".to_string(), "gist".to_string()])
+            }
+            _ => panic!("expected missing scopes"),
+        }
+        assert!(check_token_scopes(Some("")).is_err());
+    }
+
+    #[test]
+    fn test_is_prerelease() {
+        assert!(is_prerelease("1.0rc1"));
+        assert!(is_prerelease("1.0.0-beta.2"));
+        assert!(is_prerelease("2.0.dev1"));
+        assert!(is_prerelease("0.3a1"));
+        assert!(!is_prerelease("1.0.0"));
+        assert!(!is_prerelease("0.20"));
+        assert!(!is_prerelease("1.0.0+build5"));
+    }
 }
@@ -1,11 +1,15 @@
+pub mod appstream;
 pub mod cargo;
+pub mod citation;
 pub mod config;
+pub mod copyright;
 pub mod custom;
 pub mod github;
 pub mod launchpad;
 pub mod manpage;
 pub mod news_file;
 pub mod project_config;
+pub mod pypi;
 pub mod python;
 pub mod version;
 use breezyshim::branch::Branch;
@@ -160,35 +164,34 @@ pub fn find_last_version_in_files(
         log::debug!("Reading version from Cargo.toml");
         return Ok(Some((cargo::find_version(tree)?, None)));
     }
-    if tree.has_filename(Path::new("pyproject.toml")) {
-        log::debug!("Reading version from pyproject.toml");
-        if let Some(version) = python::find_version_in_pyproject_toml(tree)? {
-            return Ok(Some((version, None)));
+    match python::find_version_backend(tree)? {
+        Some(python::VersionBackend::Vcs("hatch-vcs")) => {
+            log::debug!("Reading version from hatch-vcs");
+            if let Some(version) = python::find_hatch_vcs_version(tree) {
+                return Ok(Some((version, None)));
+            }
+            log::warn!("hatch-vcs in use but unable to determine version");
         }
-        if python::pyproject_uses_hatch_vcs(tree)? {
-            let version = if let Some(version) = python::find_hatch_vcs_version(tree) {
-                version
-            } else {
-                unimplemented!("hatch in use but unable to find hatch vcs version");
-            };
-            return Ok(Some((version, None)));
+        Some(python::VersionBackend::Vcs(tool)) => {
+            log::debug!("Version is derived from VCS tags by {}", tool);
         }
+        Some(backend) => {
+            log::debug!("Reading version from Python project ({:?})", backend);
+            if let Some(version) = python::find_version_in_python_project(tree)? {
+                return Ok(Some((version, None)));
+            }
+        }
+        None => {}
     }
     for update_cfg in cfg.update_version.as_ref().unwrap_or(&Vec::new()) {
         let path = &update_cfg.path;
         let new_line = &update_cfg.new_line;
         log::debug!("Reading version from {}", path.display());
-        let f = tree.get_file(path).unwrap();
-        use std::io::BufRead;
-        let buf = std::io::BufReader::new(f);
-        let lines = buf.lines().map(|l| l.unwrap()).collect::<Vec<_>>();
-        let (v, s) = custom::reverse_version(
+        let text = String::from_utf8(tree.get_file_text(path)?)?;
+        let (v, s) = custom::reverse_version_in_text(
             new_line.as_str(),
-            lines
-                .iter()
-                .map(|l| l.as_str())
-                .collect::<Vec<_>>()
-                .as_slice(),
+            text.as_str(),
+            update_cfg.occurrences.unwrap_or_default(),
         );
         if let Some(v) = v {
             return Ok(Some((v, s)));
@@ -287,6 +290,30 @@ pub fn iter_glob<'a>(
         .filter(|p| !local_tree.is_control_filename(p))
 }
 
+/// List the versioned files in a tree.
+pub fn versioned_files(tree: &WorkingTree) -> Result<Vec<PathBuf>, Box<breezyshim::error::Error>> {
+    let _lock = tree.lock_read()?;
+    let paths = pyo3::Python::with_gil(|py| {
+        use pyo3::prelude::*;
+        tree.to_object(py)
+            .call_method0(py, "all_versioned_paths")?
+            .bind(py)
+            .iter()?
+            .map(|p| p?.extract::<PathBuf>())
+            .collect::<pyo3::PyResult<Vec<_>>>()
+    })
+    .map_err(breezyshim::error::Error::from)?;
+
+    let mut ret = vec![];
+    for path in paths {
+        if tree.kind(&path)? == breezyshim::tree::Kind::File {
+            ret.push(path);
+        }
+    }
+    ret.sort();
+    Ok(ret)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
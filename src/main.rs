@@ -1,6 +1,7 @@
 use breezyshim::error::Error as BrzError;
 use breezyshim::tree::{MutableTree, Tree};
 use breezyshim::workingtree::{self, WorkingTree};
+use chrono::Datelike;
 use clap::Parser;
 use disperse::project_config::{read_project_with_fallback, ProjectConfig};
 use disperse::version::Version;
@@ -101,6 +102,13 @@ struct Args {
     #[clap(long)]
     prometheus: Option<String>,
 
+    /// Fail instead of prompting for missing credentials; implied if stdin is
+    /// not a terminal. GitHub credentials are looked up in GITHUB_TOKEN, the
+    /// keyring, the configured token or token file, and the configured GitHub
+    /// App, in that order
+    #[clap(long)]
+    non_interactive: bool,
+
     #[command(subcommand)]
     command: Commands,
 }
@@ -163,9 +171,9 @@ struct ReleaseArgs {
 
 #[derive(clap::Args)]
 struct DiscoverArgs {
-    /// Pypi users to upload for
-    #[clap(long, env = "PYPI_USERNAME")]
-    pypi_user: Vec<String>,
+    /// PyPI projects to discover; defaults to the configured projects
+    #[clap(long)]
+    pypi_project: Vec<String>,
 
     /// Crates.io users to upload for
     #[clap(long, env = "CRATES_IO_USERNAME")]
@@ -490,7 +498,9 @@ pub enum ReleaseError {
     CIFailed(String),
     CIPending(String),
     PublishArtifactsFailed(String),
-    DistCreationFailed,
+    DistCreationFailed(String),
+    /// Packaging the crate for crates.io failed.
+    CargoPackageFailed(String),
     NoPublicBranch,
     Other(String),
 }
@@ -570,7 +580,12 @@ impl std::fmt::Display for ReleaseError {
             ReleaseError::PublishArtifactsFailed(msg) => {
                 write!(f, "Publish artifacts failed: {}", msg)
             }
-            ReleaseError::DistCreationFailed => write!(f, "Dist creation failed"),
+            ReleaseError::DistCreationFailed(reason) => {
+                write!(f, "Dist creation failed: {}", reason)
+            }
+            ReleaseError::CargoPackageFailed(reason) => {
+                write!(f, "Cargo packaging failed: {}", reason)
+            }
             ReleaseError::NoPublicBranch => write!(f, "No public branch"),
         }
     }
@@ -601,6 +616,49 @@ impl std::fmt::Display for RecentCommits {
 
 impl std::error::Error for RecentCommits {}
 
+/// Check that the crates in the tree can be packaged, before anything
+/// irreversible happens.
+fn check_cargo_packages(
+    local_tree: &WorkingTree,
+    registry: Option<&str>,
+) -> Result<(), ReleaseError> {
+    let members = disperse::cargo::workspace_members(local_tree)
+        .map_err(|e| ReleaseError::CargoPackageFailed(e.to_string()))?;
+    let publishable = disperse::cargo::publish_order(&members)
+        .map_err(|e| ReleaseError::CargoPackageFailed(e.to_string()))?
+        .into_iter()
+        .filter(|m| m.may_publish_to(registry))
+        .collect::<Vec<_>>();
+    if publishable.is_empty() {
+        return Ok(());
+    }
+    let versioned = disperse::versioned_files(local_tree)
+        .map_err(|e| ReleaseError::CargoPackageFailed(e.to_string()))?;
+    for member in publishable.iter() {
+        let files = disperse::cargo::package_file_list(local_tree, member).map_err(|e| {
+            log::error!("{}", e.output);
+            ReleaseError::CargoPackageFailed(e.to_string())
+        })?;
+        let unversioned = disperse::cargo::unversioned_package_files(member, &files, &versioned);
+        if !unversioned.is_empty() {
+            return Err(ReleaseError::CargoPackageFailed(format!(
+                "{} would include unversioned files: {}",
+                member.name,
+                unversioned
+                    .iter()
+                    .map(|p| p.display().to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )));
+        }
+    }
+    log::info!("Running cargo publish --dry-run");
+    disperse::cargo::publish_dry_run(local_tree, &publishable, registry).map_err(|e| {
+        log::error!("{}", e.output);
+        ReleaseError::CargoPackageFailed(e.to_string())
+    })
+}
+
 fn check_release_age(
     branch: &dyn breezyshim::branch::Branch,
     cfg: &ProjectConfig,
@@ -624,41 +682,104 @@ fn check_release_age(
     Ok(())
 }
 
+/// Find the web API of the Cargo registry to publish to.
+///
+/// The API URL comes from the global configuration if set there, and for
+/// alternative registries is otherwise looked up from the registry index.
+async fn cargo_api_url(
+    local_tree: &WorkingTree,
+    registry: Option<&str>,
+    config: &disperse::config::Config,
+) -> Result<Option<Url>, disperse::cargo::Error> {
+    let Some(registry) = registry else {
+        return Ok(config
+            .crates_io
+            .as_ref()
+            .and_then(|crates_io| crates_io.api_url.clone()));
+    };
+    if let Some(api_url) = config
+        .cargo_registries
+        .as_ref()
+        .and_then(|registries| registries.get(registry))
+        .and_then(|r| r.api_url.clone())
+    {
+        return Ok(Some(api_url));
+    }
+    let index = disperse::cargo::find_registry_index(local_tree, registry)?.ok_or_else(|| {
+        disperse::cargo::Error::Other(format!("Cargo registry {} is not configured", registry))
+    })?;
+    disperse::cargo::registry_api_url(&index).await.map(Some)
+}
+
+/// Log in to the GitHub instance hosting `repo_url`.
+async fn github_client(
+    repo_url: &Url,
+    config: &disperse::config::Config,
+) -> Result<octocrab::Octocrab, ReleaseError> {
+    let host_config = repo_url.host_str().and_then(|host| {
+        config
+            .github_hosts
+            .as_ref()
+            .and_then(|hosts| hosts.get(host))
+    });
+    disperse::github::login(repo_url, host_config, !config.non_interactive)
+        .await
+        .map_err(|e| ReleaseError::Other(e.to_string()))
+}
+
 async fn publish_artifacts(
     ws: &silver_platter::workspace::Workspace,
     tag_name: &str,
     dry_run: bool,
-    gh: &octocrab::Octocrab,
     cfg: &ProjectConfig,
     pypi_paths: &[&std::path::Path],
-    gh_repo: Option<&octocrab::models::Repository>,
+    github: Option<(&octocrab::Octocrab, &octocrab::models::Repository)>,
+    config: &disperse::config::Config,
 ) -> Result<Vec<std::path::PathBuf>, ReleaseError> {
     let mut artifacts = vec![];
     // Wait for CI to go green
-    if let Some(gh_repo) = gh_repo {
+    if let Some((gh, gh_repo)) = github {
         if dry_run {
             log::info!("In dry-run mode, so unable to wait for CI");
         } else {
-            disperse::github::wait_for_gh_actions(gh, gh_repo, Some(tag_name), cfg.ci_timeout)
-                .await
-                .map_err(|e| ReleaseError::CIFailed(e.to_string()))?;
+            let status = disperse::github::wait_for_gh_actions(
+                gh,
+                gh_repo,
+                Some(tag_name),
+                cfg.github.as_ref().and_then(|g| g.branch.as_deref()),
+                cfg.ci.as_ref().unwrap_or(&Default::default()),
+                cfg.ci_timeout.map(std::time::Duration::from_secs),
+            )
+            .await
+            .map_err(|e| ReleaseError::CIFailed(e.to_string()))?;
+            if !status.is_ok() {
+                return Err(ReleaseError::CIFailed(status.to_string()));
+            }
         }
     }
 
     if !pypi_paths.is_empty() {
         artifacts.extend(pypi_paths.iter().map(|x| x.to_path_buf()));
         if dry_run {
-            log::info!("skipping twine upload due to dry run mode")
+            log::info!("skipping PyPI upload due to dry run mode")
         } else if !cfg.twine_upload.unwrap_or(false) {
-            log::info!("skipping twine upload; disabled in config")
+            log::info!("skipping PyPI upload; disabled in config")
         } else {
-            disperse::python::upload_python_artifacts(ws.local_tree(), pypi_paths).map_err(
-                |e| ReleaseError::UploadCommandFailed {
-                    command: "twine upload".to_string(),
+            let repository_url = config
+                .pypi
+                .as_ref()
+                .and_then(|pypi| pypi.repository_url.as_deref());
+            let index_url = config
+                .pypi
+                .as_ref()
+                .and_then(|pypi| pypi.index_url.as_ref());
+            disperse::pypi::upload_python_artifacts(repository_url, index_url, pypi_paths)
+                .await
+                .map_err(|e| ReleaseError::UploadCommandFailed {
+                    command: "PyPI upload".to_string(),
                     status: None,
                     reason: Some(e.to_string()),
-                },
-            )?;
+                })?;
         }
     }
     if ws
@@ -668,13 +789,18 @@ async fn publish_artifacts(
         if dry_run {
             log::info!("skipping cargo upload due to dry run mode");
         } else {
-            disperse::cargo::publish(ws.local_tree(), std::path::Path::new(".")).map_err(|e| {
-                ReleaseError::UploadCommandFailed {
-                    command: "cargo publish".to_string(),
-                    status: None,
-                    reason: Some(e.to_string()),
-                }
-            })?;
+            let registry = cfg.cargo_registry.as_deref();
+            let upload_failed = |e: disperse::cargo::Error| ReleaseError::UploadCommandFailed {
+                command: "cargo publish".to_string(),
+                status: None,
+                reason: Some(e.to_string()),
+            };
+            let api_url = cargo_api_url(ws.local_tree(), registry, config)
+                .await
+                .map_err(upload_failed)?;
+            disperse::cargo::publish_workspace(ws.local_tree(), api_url.as_ref(), registry)
+                .await
+                .map_err(upload_failed)?;
         }
     }
     for loc in cfg.tarball_location.iter() {
@@ -750,19 +876,31 @@ async fn launchpad_client() -> Result<&'static launchpadlib::r#async::client::Cl
         .await
 }
 
+/// Options for releasing a project.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ReleaseOptions {
+    /// Release even if there are no changes since the last release.
+    pub force: bool,
+    pub dry_run: bool,
+    pub ignore_ci: bool,
+    pub ignore_verify_command: bool,
+    /// Keep the temporary working tree around after the release.
+    pub preserve_temp: bool,
+}
+
 pub async fn release_project(
     repo_url: &str,
-    force: Option<bool>,
     new_version: Option<&Version>,
-    dry_run: Option<bool>,
-    ignore_ci: Option<bool>,
-    ignore_verify_command: Option<bool>,
-    preserve_temp: bool,
+    options: &ReleaseOptions,
+    config: &disperse::config::Config,
 ) -> Result<(String, Version), ReleaseError> {
-    let force = force.unwrap_or(false);
-    let dry_run = dry_run.unwrap_or(false);
-    let ignore_ci = ignore_ci.unwrap_or(false);
-    let ignore_verify_command = ignore_verify_command.unwrap_or(false);
+    let ReleaseOptions {
+        force,
+        dry_run,
+        ignore_ci,
+        ignore_verify_command,
+        preserve_temp,
+    } = *options;
     let now = chrono::Utc::now();
 
     let (local_wt, branch) = match breezyshim::controldir::open_tree_or_branch(repo_url, None, None)
@@ -929,23 +1067,26 @@ pub async fn release_project(
         };
 
     let mut gh_repo = None;
-
-    let gh = disperse::github::login().map_err(|e| ReleaseError::Other(e.to_string()))?;
+    let mut gh = None;
 
     if let Some(github) = cfg.github.as_ref() {
         let url = &github.url;
         public_repo_url = Some(url.parse().unwrap());
         ws.set_main_branch(breezyshim::branch::open(public_repo_url.as_ref().unwrap()).unwrap())
             .unwrap();
+        let client = github_client(public_repo_url.as_ref().unwrap(), config).await?;
         gh_repo = Some(
-            disperse::github::get_github_repo(&gh, public_repo_url.as_ref().unwrap())
+            disperse::github::get_github_repo(&client, public_repo_url.as_ref().unwrap())
                 .await
                 .map_err(|e| ReleaseError::Other(e.to_string()))?,
         );
+        let client = gh.insert(client);
         match disperse::github::check_gh_repo_action_status(
-            &gh,
+            client,
             gh_repo.as_ref().unwrap(),
             github.branch.as_deref(),
+            github.branch.as_deref(),
+            cfg.ci.as_ref().unwrap_or(&Default::default()),
         )
         .await
         {
@@ -1020,19 +1161,23 @@ pub async fn release_project(
 
     for (parsed_url, branch_name) in possible_urls.iter() {
         match parsed_url.host_str() {
-            Some("github.com") => {
+            Some(host) if disperse::github::is_github_host(host, config.github_hosts.as_ref()) => {
                 if gh_repo.is_some() {
                     continue;
                 }
+                let client = github_client(parsed_url, config).await?;
                 gh_repo = Some(
-                    disperse::github::get_github_repo(&gh, parsed_url)
+                    disperse::github::get_github_repo(&client, parsed_url)
                         .await
                         .map_err(|e| ReleaseError::Other(e.to_string()))?,
                 );
+                let client = gh.insert(client);
                 match disperse::github::check_gh_repo_action_status(
-                    &gh,
+                    client,
                     gh_repo.as_ref().unwrap(),
                     branch_name.as_deref(),
+                    branch_name.as_deref(),
+                    cfg.ci.as_ref().unwrap_or(&Default::default()),
                 )
                 .await
                 {
@@ -1176,9 +1321,7 @@ pub async fn release_project(
     for update_version in cfg.update_version.as_ref().unwrap_or(&vec![]) {
         disperse::custom::update_version_in_file(
             ws.local_tree(),
-            &update_version.path,
-            &update_version.new_line,
-            update_version.r#match.as_deref(),
+            update_version,
             &new_version,
             disperse::Status::Final,
         )
@@ -1190,6 +1333,7 @@ pub async fn release_project(
             disperse::manpage::update_version_in_manpage(
                 ws.local_tree(),
                 &path,
+                &name,
                 &new_version,
                 now.date_naive(),
             )
@@ -1197,12 +1341,55 @@ pub async fn release_project(
         }
     }
 
+    let copyright_holder = cfg
+        .copyright_holder
+        .clone()
+        .or_else(|| disperse::copyright::find_project_author(ws.local_tree()));
+    for update_copyright in cfg.update_copyright.as_ref().unwrap_or(&vec![]) {
+        for path in disperse::iter_glob(ws.local_tree(), update_copyright.to_str().unwrap()) {
+            disperse::copyright::update_copyright_in_file(
+                ws.local_tree(),
+                &path,
+                now.date_naive().year(),
+                copyright_holder.as_deref(),
+            )
+            .map_err(|e| ReleaseError::Other(format!("{}: {}", path.display(), e)))?;
+        }
+    }
+
+    if ws
+        .local_tree()
+        .has_filename(Path::new(disperse::citation::CITATION_FILE))
+    {
+        disperse::citation::update_citation_cff_in_tree(
+            ws.local_tree(),
+            Path::new(disperse::citation::CITATION_FILE),
+            &new_version,
+            now.date_naive(),
+        )
+        .map_err(|e| ReleaseError::Other(e.to_string()))?;
+    }
+
+    for path in disperse::appstream::find_metainfo_files(ws.local_tree()) {
+        disperse::appstream::update_metainfo_in_tree(
+            ws.local_tree(),
+            &path,
+            &new_version,
+            now.date_naive(),
+            release_changes.as_deref(),
+        )
+        .map_err(|e| ReleaseError::Other(format!("{}: {}", path.display(), e)))?;
+    }
+
     if ws.local_tree().has_filename(Path::new("Cargo.toml")) {
         disperse::cargo::update_version(ws.local_tree(), new_version.to_string().as_str())
             .map_err(|e| ReleaseError::Other(e.to_string()))?;
     }
-    if ws.local_tree().has_filename(Path::new("pyproject.toml")) {
-        disperse::python::update_version_in_pyproject_toml(ws.local_tree(), &new_version)
+    if ["pyproject.toml", "setup.cfg", "setup.py"]
+        .iter()
+        .any(|f| ws.local_tree().has_filename(Path::new(f)))
+    {
+        disperse::python::update_version_in_python_project(ws.local_tree(), &new_version)
             .map_err(|e| ReleaseError::Other(e.to_string()))?;
     }
     let revid = ws
@@ -1242,6 +1429,13 @@ pub async fn release_project(
         }
     }
 
+    if ws
+        .local_tree()
+        .has_filename(std::path::Path::new("Cargo.toml"))
+    {
+        check_cargo_packages(ws.local_tree(), cfg.cargo_registry.as_deref())?;
+    }
+
     let tag_name = disperse::version::expand_tag(cfg.tag_name.as_ref().unwrap(), &new_version);
     let tags = ws.local_tree().branch().tags().unwrap();
     if tags.has_tag(tag_name.as_str()) {
@@ -1255,6 +1449,89 @@ pub async fn release_project(
             tag: tag_name,
         });
     }
+    let maturin = disperse::python::uses_maturin(ws.local_tree())
+        .map_err(|e| ReleaseError::Other(e.to_string()))?;
+    log::info!("Creating Python artifacts");
+    let mut pypi_paths = if maturin {
+        disperse::python::create_maturin_sdist(ws.local_tree()).map_err(|e| {
+            log::error!("{}", e.output);
+            ReleaseError::DistCreationFailed(e.to_string())
+        })?
+    } else if ws.local_tree().has_filename(Path::new("setup.py"))
+        || ws.local_tree().has_filename(Path::new("pyproject.toml"))
+    {
+        disperse::python::create_python_artifacts(ws.local_tree(), cfg.python.as_deref()).map_err(
+            |e| {
+                log::error!("{}", e.output);
+                ReleaseError::DistCreationFailed(e.to_string())
+            },
+        )?
+    } else {
+        vec![]
+    };
+
+    let wheel_builder = cfg
+        .binary_wheels
+        .or(maturin.then_some(disperse::project_config::WheelBuilder::Maturin));
+    if let Some(builder) = wheel_builder {
+        log::info!("Building binary wheels");
+        let wheels =
+            disperse::python::build_binary_wheels(ws.local_tree(), builder, cfg.python.as_deref())
+                .map_err(|e| {
+                    log::error!("{}", e.output);
+                    ReleaseError::DistCreationFailed(e.to_string())
+                })?;
+        pypi_paths.splice(0..0, wheels);
+    }
+
+    for path in pypi_paths.iter() {
+        let dist = disperse::pypi::Distribution::open(path)
+            .map_err(|e| ReleaseError::DistCreationFailed(format!("{}: {}", path.display(), e)))?;
+        let problems = disperse::pypi::check_distribution(
+            &dist,
+            &new_version.to_string(),
+            cfg.python.as_deref(),
+        );
+        if !problems.is_empty() {
+            return Err(ReleaseError::DistCreationFailed(format!(
+                "{}: {}",
+                dist.filename,
+                problems.join("; ")
+            )));
+        }
+    }
+
+    let default_sdist_check = disperse::project_config::SdistCheck::default();
+    let sdist_check = cfg.sdist_check.as_ref().unwrap_or(&default_sdist_check);
+    for path in pypi_paths.iter() {
+        if !path.to_string_lossy().ends_with(".tar.gz") {
+            continue;
+        }
+        let missing = disperse::versioned_files(ws.local_tree())
+            .map_err(disperse::python::Error::BrzError)
+            .and_then(|versioned| {
+                let sdist_files = disperse::python::sdist_file_list(path)?;
+                disperse::python::missing_from_sdist(&versioned, &sdist_files, &sdist_check.ignore)
+            })
+            .map_err(|e| ReleaseError::DistCreationFailed(format!("{}: {}", path.display(), e)))?;
+        if missing.is_empty() {
+            continue;
+        }
+        let missing = missing
+            .iter()
+            .map(|p| p.display().to_string())
+            .collect::<Vec<_>>()
+            .join(", ");
+        if sdist_check.strict {
+            return Err(ReleaseError::DistCreationFailed(format!(
+                "{} is missing versioned files: {}",
+                path.display(),
+                missing
+            )));
+        }
+        log::warn!("{} is missing versioned files: {}", path.display(), missing);
+    }
+
     log::info!("Creating tag {}", tag_name);
     if is_git_repo(&ws.local_tree().branch().repository()) {
         match std::process::Command::new("git")
@@ -1292,15 +1569,6 @@ pub async fn release_project(
             })?;
     }
 
-    log::info!("Creating Python artifacts");
-    let pypi_paths = if ws.local_tree().has_filename(Path::new("setup.py")) {
-        disperse::python::create_setup_py_artifacts(ws.local_tree()).unwrap()
-    } else if ws.local_tree().has_filename(Path::new("pyproject.toml")) {
-        disperse::python::create_python_artifacts(ws.local_tree()).unwrap()
-    } else {
-        vec![]
-    };
-
     if !dry_run {
         log::info!(
             "Pushing tag {} to {}",
@@ -1321,14 +1589,14 @@ pub async fn release_project(
         &ws,
         &tag_name,
         dry_run,
-        &gh,
         &cfg,
         pypi_paths
             .iter()
             .map(|p| p.as_path())
             .collect::<Vec<_>>()
             .as_slice(),
-        gh_repo.as_ref(),
+        gh.as_ref().zip(gh_repo.as_ref()),
+        config,
     )
     .await;
 
@@ -1400,19 +1668,37 @@ pub async fn release_project(
         }
     }
 
-    if let Some(gh_repo) = gh_repo.as_ref() {
+    if let Some((gh, gh_repo)) = gh.as_ref().zip(gh_repo.as_ref()) {
         if dry_run {
             log::info!("skipping creation of github release due to dry run mode");
         } else {
-            disperse::github::create_github_release(
-                &gh,
+            let release = disperse::github::create_github_release(
+                gh,
                 gh_repo,
                 tag_name.as_str(),
                 &new_version.to_string(),
                 release_changes.as_deref(),
+                cfg.github_release.as_ref().unwrap_or(&Default::default()),
+            )
+            .await
+            .map_err(|e| ReleaseError::Other(e.to_string()))?;
+            let mut assets = artifacts.clone();
+            for pattern in cfg.release_assets.iter() {
+                for path in disperse::iter_glob(ws.local_tree(), pattern) {
+                    assets.push(ws.local_tree().abspath(&path).unwrap());
+                }
+            }
+            disperse::github::upload_release_assets(
+                gh,
+                gh_repo,
+                &release,
+                &disperse::github::release_asset_paths(&assets),
             )
             .await
             .map_err(|e| ReleaseError::Other(e.to_string()))?;
+            disperse::github::publish_github_release(gh, gh_repo, &release)
+                .await
+                .map_err(|e| ReleaseError::Other(e.to_string()))?;
         }
     }
 
@@ -1493,12 +1779,9 @@ pub async fn release_project(
 async fn release_many(
     urls: &[String],
     new_version: Option<String>,
-    ignore_ci: Option<bool>,
-    ignore_verify_command: Option<bool>,
-    dry_run: Option<bool>,
     discover: bool,
-    force: Option<bool>,
-    preserve_temp: bool,
+    options: &ReleaseOptions,
+    config: &disperse::config::Config,
 ) -> i32 {
     let mut failed: Vec<(String, String)> = Vec::new();
     let mut skipped: Vec<(String, String)> = Vec::new();
@@ -1510,15 +1793,12 @@ async fn release_many(
         }
         match release_project(
             url,
-            force,
             new_version
                 .as_ref()
                 .map(|v| v.as_str().parse().unwrap())
                 .as_ref(),
-            dry_run,
-            ignore_ci,
-            ignore_verify_command,
-            preserve_temp,
+            options,
+            config,
         )
         .await
         {
@@ -1582,9 +1862,20 @@ async fn release_many(
                     ret = 1;
                 }
             }
-            Err(ReleaseError::DistCreationFailed) => {
-                log::error!("Dist creation failed to run.");
-                failed.push((url.to_string(), "Dist creation failed to run.".to_string()));
+            Err(ReleaseError::DistCreationFailed(reason)) => {
+                log::error!("Dist creation failed to run: {}", reason);
+                failed.push((
+                    url.to_string(),
+                    format!("Dist creation failed to run: {}", reason),
+                ));
+                ret = 1;
+            }
+            Err(ReleaseError::CargoPackageFailed(reason)) => {
+                log::error!("Cargo packaging failed: {}", reason);
+                failed.push((
+                    url.to_string(),
+                    format!("Cargo packaging failed: {}", reason),
+                ));
                 ret = 1;
             }
             Err(ReleaseError::NoUnreleasedChanges) => {
@@ -1731,6 +2022,41 @@ fn validate_config(path: &std::path::Path) -> i32 {
         }
     }
 
+    let copyright_holder = cfg
+        .copyright_holder
+        .clone()
+        .or_else(|| disperse::copyright::find_project_author(&wt));
+    for update_copyright in cfg.update_copyright.unwrap_or_default().iter() {
+        for path in disperse::iter_glob(&wt, update_copyright.to_str().unwrap()) {
+            match disperse::copyright::validate_update_copyright(
+                &wt,
+                path.as_path(),
+                copyright_holder.as_deref(),
+            ) {
+                Ok(_) => {}
+                Err(e) => {
+                    log::error!("Invalid update_copyright: {}: {}", path.display(), e);
+                    return 1;
+                }
+            }
+        }
+    }
+
+    let citation_path = Path::new(disperse::citation::CITATION_FILE);
+    if wt.has_filename(citation_path) {
+        if let Err(e) = disperse::citation::validate_citation_cff(&wt, citation_path) {
+            log::error!("Unable to update {}: {}", citation_path.display(), e);
+            return 1;
+        }
+    }
+
+    for path in disperse::appstream::find_metainfo_files(&wt) {
+        if let Err(e) = disperse::appstream::validate_metainfo(&wt, path.as_path()) {
+            log::error!("Unable to update {}: {}", path.display(), e);
+            return 1;
+        }
+    }
+
     0
 }
 
@@ -1888,7 +2214,8 @@ async fn main() {
         )
         .init();
 
-    let config = disperse::config::load_config().unwrap().unwrap_or_default();
+    let mut config = disperse::config::load_config().unwrap().unwrap_or_default();
+    config.non_interactive |= args.non_interactive;
 
     log::debug!("Config: {:?}", config);
 
@@ -1902,47 +2229,119 @@ async fn main() {
             release_many(
                 release_args.url.as_slice(),
                 release_args.new_version.clone(),
-                Some(release_args.ignore_ci),
-                Some(release_args.ignore_verify_command),
-                Some(args.dry_run),
                 release_args.discover,
-                Some(true),
-                release_args.preserve_temp,
+                &ReleaseOptions {
+                    force: true,
+                    dry_run: args.dry_run,
+                    ignore_ci: release_args.ignore_ci,
+                    ignore_verify_command: release_args.ignore_verify_command,
+                    preserve_temp: release_args.preserve_temp,
+                },
+                &config,
             )
             .await
         }
         Commands::Discover(discover_args) => {
-            let pypi_usernames = match discover_args.pypi_user.as_slice() {
+            let pypi_projects = match discover_args.pypi_project.as_slice() {
                 [] => config
                     .pypi
-                    .map(|pypi| vec![pypi.username])
+                    .as_ref()
+                    .and_then(|pypi| pypi.projects.clone())
                     .unwrap_or_default(),
-                pypi_usernames => pypi_usernames.to_vec(),
+                pypi_projects => pypi_projects.to_vec(),
             };
 
             let crates_io_user = match discover_args.crates_io_user.as_ref() {
-                Option::None => config.crates_io.map(|crates_io| crates_io.username),
+                Option::None => config
+                    .crates_io
+                    .as_ref()
+                    .and_then(|crates_io| crates_io.username.clone()),
                 Some(crates_io_user) => Some(crates_io_user.clone()),
             };
 
-            let pypi_urls = pypi_usernames
-                .iter()
-                .flat_map(|pypi_username| disperse::python::pypi_discover_urls(pypi_username))
-                .flatten()
-                .collect::<Vec<_>>();
+            let index_url = config
+                .pypi
+                .as_ref()
+                .and_then(|pypi| pypi.index_url.as_ref());
+            let pypi_urls = if pypi_projects.is_empty() {
+                if config
+                    .pypi
+                    .as_ref()
+                    .is_some_and(|pypi| pypi.username.is_some())
+                {
+                    log::warn!(
+                        "PyPI can not list the projects of a user; set projects in the [pypi] section to discover them"
+                    );
+                }
+                vec![]
+            } else {
+                match disperse::python::pypi_project_urls(
+                    &pypi_projects,
+                    index_url,
+                    config.github_hosts.as_ref(),
+                )
+                .await
+                {
+                    Ok(urls) => urls,
+                    Err(e) => {
+                        log::error!("Unable to discover PyPI projects: {}", e);
+                        vec![]
+                    }
+                }
+            };
 
-            let crates_io_urls = match crates_io_user {
+            let mut owned_crates = match crates_io_user {
                 Option::None => {
                     vec![]
                 }
-                Some(crates_io_user) => {
-                    disperse::cargo::get_owned_crates(crates_io_user.as_str()).unwrap()
-                }
+                Some(crates_io_user) => match disperse::cargo::get_owned_crates(
+                    config
+                        .crates_io
+                        .as_ref()
+                        .and_then(|crates_io| crates_io.api_url.as_ref()),
+                    crates_io_user.as_str(),
+                )
+                .await
+                {
+                    Ok(crates) => crates,
+                    Err(e) => {
+                        log::error!("Unable to discover crates: {}", e);
+                        vec![]
+                    }
+                },
             };
 
+            for (name, registry) in config.cargo_registries.iter().flatten() {
+                let (Some(username), Some(api_url)) =
+                    (registry.username.as_ref(), registry.api_url.as_ref())
+                else {
+                    continue;
+                };
+                match disperse::cargo::get_owned_crates(Some(api_url), username).await {
+                    Ok(crates) => owned_crates.extend(crates),
+                    Err(e) => log::error!("Unable to discover crates in {}: {}", name, e),
+                }
+            }
+
+            let mut crates_io_urls: Vec<Url> = vec![];
+            for owned_crate in owned_crates {
+                if let Some(subpath) = owned_crate.subpath.as_ref() {
+                    log::debug!(
+                        "Crate {} lives in {} of {}",
+                        owned_crate.name,
+                        subpath.display(),
+                        owned_crate.repository
+                    );
+                }
+                if !crates_io_urls.contains(&owned_crate.repository) {
+                    crates_io_urls.push(owned_crate.repository);
+                }
+            }
+
             let repositories_urls = config
                 .repositories
-                .and_then(|repositories| repositories.owned)
+                .as_ref()
+                .and_then(|repositories| repositories.owned.clone())
                 .unwrap_or_default();
 
             let urls: Vec<Url> = vec![pypi_urls, crates_io_urls, repositories_urls]
@@ -1972,12 +2371,9 @@ async fn main() {
                             .collect::<Vec<_>>()
                             .as_slice(),
                         None,
-                        Some(false),
-                        Some(false),
-                        Some(false),
                         true,
-                        Some(false),
-                        false,
+                        &ReleaseOptions::default(),
+                        &config,
                     )
                     .await
                 };
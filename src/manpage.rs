@@ -46,66 +46,451 @@ impl std::fmt::Display for Error {
 
 impl std::error::Error for Error {}
 
-/// Update the version in a manpage.
-pub fn update_version_in_manpage(
-    tree: &dyn MutableTree,
-    path: &Path,
-    new_version: &Version,
-    release_date: NaiveDate,
-) -> Result<(), Error> {
-    let file = tree.get_file(path)?;
-
-    let mut lines = BufReader::new(file)
-        .split(b'\n')
-        .collect::<Result<Vec<_>, _>>()?;
-
+/// Update the date and version in a man(7) `.TH` line.
+fn update_th_line(line: &str, new_version: &Version, release_date: NaiveDate) -> Option<String> {
     let date_options: Vec<(&str, &str)> = vec![
         (r"20[0-9][0-9]-[0-1][0-9]-[0-3][0-9]", "%Y-%m-%d"),
         (r"[A-Za-z]+ ([0-9]{4})", "%B %Y"),
     ];
 
-    let version_options: Vec<(&str, &str)> = vec![(r"([^ ]+) ([0-9a-z.]+)", r"\1 $VERSION")];
+    let version_options: Vec<(&str, &str)> = vec![(r"([^ ]+) ([0-9a-z.]+)", "${1} $VERSION")];
 
-    for (i, line) in lines.iter_mut().enumerate() {
-        if !line.starts_with(&b".TH "[..]) {
-            continue;
+    let mut args = shlex::split(line)?;
+
+    if args.len() < 5 {
+        return None;
+    }
+
+    // Iterate through date options
+    for (r, f) in &date_options {
+        let re = Regex::new(r).unwrap();
+        if let Some(_captures) = re.captures(&args[3]) {
+            let formatted_date = release_date.format(f).to_string();
+            args[3] = formatted_date;
+            break;
         }
+    }
 
-        let mut args = match shlex::split(String::from_utf8_lossy(line).as_ref()) {
-            Some(args) => args,
-            None => continue,
-        };
+    // Iterate through version options
+    for (r, f) in &version_options {
+        let re = Regex::new(r).unwrap();
+        if let Some(captures) = re.captures(&args[4]) {
+            let version_str = captures.get(0).unwrap().as_str();
+            let formatted_version = re.replace(
+                version_str,
+                f.replace("$VERSION", new_version.to_string().as_str()),
+            );
+            args[4] = formatted_version.to_string();
+            break;
+        }
+    }
 
-        // Iterate through date options
-        for (r, f) in &date_options {
-            let re = Regex::new(r)?;
-            if let Some(_captures) = re.captures(&args[3]) {
-                let formatted_date = release_date.format(f).to_string();
-                args[3] = formatted_date;
-                break;
+    Some(shlex::try_join(args.iter().map(|s| s.as_ref())).unwrap())
+}
+
+/// Replace a version at the end of `text` (e.g. "disperse 0.1.0") with `new_version`.
+fn replace_trailing_version(text: &str, new_version: &Version) -> Option<String> {
+    let re = lazy_regex::regex!(r"^(?P<prefix>(?:.*[ v])?)[0-9]+(?:\.[0-9]+)+$");
+    if !re.is_match(text) {
+        return None;
+    }
+    Some(
+        re.replace(text, format!("${{prefix}}{}", new_version.to_string()))
+            .to_string(),
+    )
+}
+
+/// Format `release_date` in the same style as the date `old`.
+fn format_date_like(old: &str, release_date: NaiveDate) -> Option<String> {
+    let format = if lazy_regex::regex_is_match!(r"^[0-9]{4}-[0-9]{2}-[0-9]{2}$", old) {
+        "%Y-%m-%d"
+    } else if lazy_regex::regex_is_match!(r"^[A-Za-z]+ [0-9]{1,2}, [0-9]{4}$", old) {
+        "%B %-d, %Y"
+    } else if lazy_regex::regex_is_match!(r"^[0-9]{1,2} [A-Za-z]+ [0-9]{4}$", old) {
+        "%-d %B %Y"
+    } else if lazy_regex::regex_is_match!(r"^[A-Za-z]+ [0-9]{4}$", old) {
+        "%B %Y"
+    } else {
+        return None;
+    };
+    Some(release_date.format(format).to_string())
+}
+
+/// Format a date the way mdoc(7) expects it in `.Dd`, preserving the style
+/// of the existing date.
+fn format_mdoc_date(old: &str, release_date: NaiveDate) -> Option<String> {
+    if old.starts_with("$Mdocdate") {
+        Some(format!("$Mdocdate: {} $", release_date.format("%B %-d %Y")))
+    } else {
+        format_date_like(old, release_date)
+    }
+}
+
+/// Update the date in an mdoc(7) `.Dd` line.
+fn update_dd_line(line: &str, release_date: NaiveDate) -> Option<String> {
+    let old = line.strip_prefix(".Dd ")?.trim();
+    let (quoted, old) = match old.strip_prefix('"').and_then(|o| o.strip_suffix('"')) {
+        Some(o) => (true, o),
+        None => (false, old),
+    };
+    let new = format_mdoc_date(old, release_date)?;
+    Some(if quoted {
+        format!(".Dd \"{}\"", new)
+    } else {
+        format!(".Dd {}", new)
+    })
+}
+
+/// Update the version in an mdoc(7) `.Os` line of the form
+/// `.Os <project-name> <version>`.
+///
+/// Other `.Os` lines, such as `.Os Linux 5.10`, name the operating system
+/// rather than the project and are left alone.
+fn update_os_line(line: &str, project_name: &str, new_version: &Version) -> Option<String> {
+    let mut args = shlex::split(line)?;
+    let arg = match args.as_slice() {
+        [_, name, version] if name.eq_ignore_ascii_case(project_name) => {
+            replace_trailing_version(version, new_version).map(|v| (2, v))
+        }
+        [_, name_version] => {
+            let (name, _) = name_version.split_once(' ')?;
+            if !name.eq_ignore_ascii_case(project_name) {
+                return None;
             }
+            replace_trailing_version(name_version, new_version).map(|v| (1, v))
         }
+        _ => None,
+    };
+    let (i, updated) = arg?;
+    args[i] = updated;
+    Some(shlex::try_join(args.iter().map(|s| s.as_ref())).unwrap())
+}
 
-        // Iterate through version options
-        for (r, f) in &version_options {
-            let re = Regex::new(r)?;
-            if let Some(captures) = re.captures(&args[4]) {
-                let version_str = captures.get(0).unwrap().as_str();
-                let formatted_version = re.replace(
-                    version_str,
-                    f.replace("$VERSION", new_version.to_string().as_str()),
-                );
-                args[4] = formatted_version.to_string();
-                break;
+/// Update the date and version in the lines of a man(7) or mdoc(7) page.
+///
+/// Returns false if the page has neither a `.TH` line nor a `.Dd` line
+/// with a recognised date.
+fn update_roff_lines(
+    lines: &mut [Vec<u8>],
+    project_name: &str,
+    new_version: &Version,
+    release_date: NaiveDate,
+) -> bool {
+    let mut found = false;
+    let mut updated_th = false;
+
+    map_lines(lines, |_, text| {
+        if text.starts_with(".TH ") {
+            if updated_th {
+                return None;
             }
+            let updated = update_th_line(text, new_version, release_date);
+            updated_th = updated.is_some();
+            found |= updated_th;
+            updated
+        } else if text.starts_with(".Dd ") {
+            let updated = update_dd_line(text, release_date);
+            found |= updated.is_some();
+            updated
+        } else if text.starts_with(".Os") {
+            update_os_line(text, project_name, new_version)
+        } else {
+            None
         }
+    });
 
-        let updated_line = shlex::try_join(args.iter().map(|s| s.as_ref())).unwrap();
-        lines[i] = updated_line.into_bytes();
-        break;
+    found
+}
+
+/// Apply `f` to each line (without its line ending), replacing lines for
+/// which it returns a new value.
+fn map_lines(lines: &mut [Vec<u8>], mut f: impl FnMut(usize, &str) -> Option<String>) {
+    for (i, line) in lines.iter_mut().enumerate() {
+        let (text, eol) = match line.strip_suffix(b"\n") {
+            Some(text) => (String::from_utf8_lossy(text).to_string(), "\n"),
+            None => (String::from_utf8_lossy(line).to_string(), ""),
+        };
+        if let Some(updated) = f(i, &text) {
+            *line = format!("{}{}", updated, eol).into_bytes();
+        }
     }
+}
+
+/// Update a date or version field value, preserving any quotes around it.
+fn update_field_value(
+    value: &str,
+    new_version: &Version,
+    release_date: Option<NaiveDate>,
+) -> Option<String> {
+    let value = value.trim();
+    let (quote, inner) = match value.chars().next() {
+        Some(q @ ('"' | '\'')) if value.len() > 1 && value.ends_with(q) => {
+            (Some(q), &value[1..value.len() - 1])
+        }
+        _ => (None, value),
+    };
+    let updated = match release_date {
+        Some(release_date) => format_date_like(inner, release_date)?,
+        None => replace_trailing_version(inner, new_version)?,
+    };
+    Some(match quote {
+        Some(q) => format!("{}{}{}", q, updated, q),
+        None => updated,
+    })
+}
+
+/// Update the version in the preamble of an scdoc(5) page.
+///
+/// The preamble looks like `name(1) "left footer" "center header"`; any of the
+/// quoted fields that ends in a version is updated. scdoc pages do not
+/// contain a date.
+fn update_scdoc_lines(lines: &mut [Vec<u8>], new_version: &Version) -> bool {
+    let mut found = false;
+    let mut seen_preamble = false;
+    map_lines(lines, |_, text| {
+        if seen_preamble || text.trim().is_empty() {
+            return None;
+        }
+        seen_preamble = true;
+        let (name, rest) = text.split_once(' ')?;
+        if !lazy_regex::regex_is_match!(r"^\S+\([0-9][a-z]*\)$", name) {
+            return None;
+        }
+        let updated = lazy_regex::regex_replace_all!(r#""([^"]*)""#, rest, |_, field: &str| {
+            match replace_trailing_version(field, new_version) {
+                Some(field) => {
+                    found = true;
+                    format!("\"{}\"", field)
+                }
+                None => format!("\"{}\"", field),
+            }
+        });
+        Some(format!("{} {}", name, updated))
+    });
+    found
+}
 
-    if lines.iter().all(|line| !line.starts_with(&b".TH "[..])) {
+/// Update the `:revnumber:`, `:revdate:` and `:mansource:` attributes of an
+/// AsciiDoc page.
+fn update_asciidoc_lines(
+    lines: &mut [Vec<u8>],
+    new_version: &Version,
+    release_date: NaiveDate,
+) -> bool {
+    let mut found = false;
+    map_lines(lines, |_, text| {
+        let (_, name, value) = lazy_regex::regex_captures!(r"^:([a-z-]+ ?[a-z-]*):\s+(.*)$", text)?;
+        let updated = match name {
+            "revnumber" => Some(new_version.to_string()),
+            "revdate" => update_field_value(value, new_version, Some(release_date)),
+            "mansource" | "man source" => update_field_value(value, new_version, None),
+            _ => None,
+        }?;
+        found = true;
+        Some(format!(":{}: {}", name, updated))
+    });
+    found
+}
+
+/// Update a `key: value` line in a YAML metadata block.
+///
+/// `date_keys` hold dates, `version_keys` hold strings that end in a version.
+fn update_yaml_line(
+    text: &str,
+    date_keys: &[&str],
+    version_keys: &[&str],
+    new_version: &Version,
+    release_date: NaiveDate,
+) -> Option<String> {
+    let (key, value) = text.split_once(':')?;
+    let updated = if date_keys.contains(&key) {
+        update_field_value(value, new_version, Some(release_date))
+    } else if version_keys.contains(&key) {
+        update_field_value(value, new_version, None)
+    } else {
+        None
+    }?;
+    Some(format!("{}: {}", key, updated))
+}
+
+/// Update the title block or YAML metadata block of a pandoc Markdown page.
+///
+/// The title block looks like:
+///
+/// ```text
+/// % NAME(1) Version 1.2.3 | User Manual
+/// % Author
+/// % January 2024
+/// ```
+///
+/// Alternatively, the `date`, `footer` and `header` keys in a leading YAML
+/// metadata block are updated.
+fn update_pandoc_lines(
+    lines: &mut [Vec<u8>],
+    new_version: &Version,
+    release_date: NaiveDate,
+) -> bool {
+    let mut found = false;
+    let mut in_yaml = false;
+    map_lines(lines, |i, text| {
+        if i == 0 && text == "---" {
+            in_yaml = true;
+            return None;
+        }
+        if in_yaml {
+            if text == "---" || text == "..." {
+                in_yaml = false;
+                return None;
+            }
+            let updated = update_yaml_line(
+                text,
+                &["date"],
+                &["footer", "header"],
+                new_version,
+                release_date,
+            )?;
+            found = true;
+            return Some(updated);
+        }
+        let field = text.strip_prefix("% ")?;
+        let updated = match i {
+            0 => {
+                let mut changed = false;
+                let parts = field
+                    .split('|')
+                    .map(|part| {
+                        let trimmed = part.trim();
+                        match replace_trailing_version(trimmed, new_version) {
+                            Some(updated) => {
+                                changed = true;
+                                part.replace(trimmed, &updated)
+                            }
+                            None => part.to_string(),
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                if changed {
+                    Some(parts.join("|"))
+                } else {
+                    None
+                }
+            }
+            2 => format_date_like(field.trim(), release_date),
+            _ => None,
+        }?;
+        found = true;
+        Some(format!("% {}", updated))
+    });
+    found
+}
+
+/// Update the `date` and `organization` fields in a ronn(7) page or
+/// `config.ronn` file.
+///
+/// Only the header is looked at: either a YAML front matter block delimited
+/// by `---`, or the leading `key: value` lines. Anything after it is part
+/// of the document.
+fn update_ronn_lines(
+    lines: &mut [Vec<u8>],
+    new_version: &Version,
+    release_date: NaiveDate,
+) -> bool {
+    let mut found = false;
+    let mut in_front_matter = false;
+    let mut in_header = true;
+    map_lines(lines, |i, text| {
+        if !in_header {
+            return None;
+        }
+        if i == 0 && text == "---" {
+            in_front_matter = true;
+            return None;
+        }
+        if in_front_matter && (text == "---" || text == "...") {
+            in_header = false;
+            return None;
+        }
+        if !in_front_matter && !lazy_regex::regex_is_match!(r"^[A-Za-z_-]+:(\s|$)", text) {
+            in_header = false;
+            return None;
+        }
+        let updated = update_yaml_line(
+            text,
+            &["date"],
+            &["organization", "manual"],
+            new_version,
+            release_date,
+        )?;
+        found = true;
+        Some(updated)
+    });
+    found
+}
+
+/// The format of a manpage, or of the source that it is generated from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// roff using either the man(7) or the mdoc(7) macros.
+    Roff,
+    Scdoc,
+    AsciiDoc,
+    PandocMarkdown,
+    Ronn,
+}
+
+impl Format {
+    /// Guess the format of a manpage from its file name.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("scd") => Format::Scdoc,
+            Some("adoc") | Some("asciidoc") => Format::AsciiDoc,
+            Some("md") | Some("markdown") => Format::PandocMarkdown,
+            Some("ronn") => Format::Ronn,
+            _ => Format::Roff,
+        }
+    }
+}
+
+/// Update the date and version in the lines of a manpage in the given format.
+///
+/// Returns false if nothing that could be updated was found.
+fn update_lines(
+    format: Format,
+    lines: &mut [Vec<u8>],
+    project_name: &str,
+    new_version: &Version,
+    release_date: NaiveDate,
+) -> bool {
+    match format {
+        Format::Roff => update_roff_lines(lines, project_name, new_version, release_date),
+        Format::Scdoc => update_scdoc_lines(lines, new_version),
+        Format::AsciiDoc => update_asciidoc_lines(lines, new_version, release_date),
+        Format::PandocMarkdown => update_pandoc_lines(lines, new_version, release_date),
+        Format::Ronn => update_ronn_lines(lines, new_version, release_date),
+    }
+}
+
+/// Update the version in a manpage.
+///
+/// Both man(7) pages (with a `.TH` line) and mdoc(7) pages (with `.Dd` and
+/// `.Os` lines) are supported, as well as scdoc, AsciiDoc, pandoc Markdown
+/// and ronn sources. The format is determined from the file extension.
+pub fn update_version_in_manpage(
+    tree: &dyn MutableTree,
+    path: &Path,
+    project_name: &str,
+    new_version: &Version,
+    release_date: NaiveDate,
+) -> Result<(), Error> {
+    let mut lines = tree.get_file_lines(path)?;
+
+    if !update_lines(
+        Format::from_path(path),
+        &mut lines,
+        project_name,
+        new_version,
+        release_date,
+    ) {
         return Err(Error::NoMatches);
     }
 
@@ -120,6 +505,19 @@ fn validate_manpage_updateable(bufread: &mut dyn BufRead) -> Result<(), Error> {
 
     let mut found = false;
     for line in lines.iter_mut() {
+        if line.starts_with(&b".Dd "[..]) {
+            if update_dd_line(
+                String::from_utf8_lossy(line).as_ref(),
+                chrono::Utc::now().date_naive(),
+            )
+            .is_some()
+            {
+                found = true;
+                break;
+            }
+            continue;
+        }
+
         if !line.starts_with(&b".TH "[..]) {
             continue;
         }
@@ -152,16 +550,251 @@ pub fn validate_update_manpage(
     tree: &dyn breezyshim::tree::Tree,
     update_manpage: &Path,
 ) -> Result<(), Error> {
-    let file = tree.get_file(update_manpage)?;
+    match Format::from_path(update_manpage) {
+        Format::Roff => {
+            let file = tree.get_file(update_manpage)?;
 
-    validate_manpage_updateable(&mut BufReader::new(file))
+            validate_manpage_updateable(&mut BufReader::new(file))
+        }
+        format => {
+            let mut lines = tree.get_file_lines(update_manpage)?;
+            if update_lines(
+                format,
+                &mut lines,
+                "",
+                &Version {
+                    major: 0,
+                    minor: Some(0),
+                    micro: Some(0),
+                },
+                chrono::Utc::now().date_naive(),
+            ) {
+                Ok(())
+            } else {
+                Err(Error::NoMatches)
+            }
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use chrono::NaiveDate;
+
     #[test]
     fn test_validate_manpage_updateable() {
         let b = b".TH BZR 1 \"2019-12-31\" \"Bazaar 2.7.0\" \"Bazaar Reference Manual\"\n";
         super::validate_manpage_updateable(&mut std::io::Cursor::new(b)).unwrap();
     }
+
+    #[test]
+    fn test_validate_mdoc_updateable() {
+        let b = b".Dd March 3, 2019\n.Dt DISPERSE 1\n.Os\n";
+        super::validate_manpage_updateable(&mut std::io::Cursor::new(b)).unwrap();
+
+        let b = b".Dd $Mdocdate$\n.Dt DISPERSE 1\n.Os\n";
+        super::validate_manpage_updateable(&mut std::io::Cursor::new(b)).unwrap();
+
+        let b = b".Dd someday\n.Dt DISPERSE 1\n.Os\n";
+        assert!(super::validate_manpage_updateable(&mut std::io::Cursor::new(b)).is_err());
+    }
+
+    fn update(text: &str) -> String {
+        let mut lines = text
+            .split_inclusive('\n')
+            .map(|l| l.as_bytes().to_vec())
+            .collect::<Vec<_>>();
+        assert!(super::update_roff_lines(
+            &mut lines,
+            "disperse",
+            &"1.2.3".parse().unwrap(),
+            NaiveDate::from_ymd_opt(2024, 1, 5).unwrap(),
+        ));
+        String::from_utf8(lines.concat()).unwrap()
+    }
+
+    #[test]
+    fn test_update_th() {
+        assert_eq!(
+            update(
+                ".TH BZR 1 \"2019-12-31\" \"Bazaar 2.7.0\" \"Bazaar Reference Manual\"\n.SH NAME\n"
+            ),
+            ".TH BZR 1 2024-01-05 'Bazaar 1.2.3' 'Bazaar Reference Manual'\n.SH NAME\n"
+        );
+    }
+
+    #[test]
+    fn test_update_mdoc() {
+        assert_eq!(
+            update(".Dd March 3, 2019\n.Dt DISPERSE 1\n.Os disperse 0.1.0\n.Sh NAME\n"),
+            ".Dd January 5, 2024\n.Dt DISPERSE 1\n.Os disperse 1.2.3\n.Sh NAME\n"
+        );
+        assert_eq!(
+            update(".Dd $Mdocdate: March 3 2019 $\n.Dt DISPERSE 1\n.Os\n"),
+            ".Dd $Mdocdate: January 5 2024 $\n.Dt DISPERSE 1\n.Os\n"
+        );
+        assert_eq!(
+            update(".Dd \"March 3, 2019\"\n.Os \"disperse 0.1\"\n"),
+            ".Dd \"January 5, 2024\"\n.Os 'disperse 1.2.3'\n"
+        );
+    }
+
+    #[test]
+    fn test_update_no_matches() {
+        let mut lines = vec![b".SH NAME\n".to_vec()];
+        assert!(!super::update_roff_lines(
+            &mut lines,
+            "disperse",
+            &"1.2.3".parse().unwrap(),
+            NaiveDate::from_ymd_opt(2024, 1, 5).unwrap(),
+        ));
+
+        let mut lines = vec![b".Dd someday\n".to_vec(), b".Os disperse 0.1\n".to_vec()];
+        assert!(!super::update_roff_lines(
+            &mut lines,
+            "disperse",
+            &"1.2.3".parse().unwrap(),
+            NaiveDate::from_ymd_opt(2024, 1, 5).unwrap(),
+        ));
+
+        // Too few arguments to have a date and version.
+        let mut lines = vec![b".TH DISPERSE 1\n".to_vec()];
+        assert!(!super::update_roff_lines(
+            &mut lines,
+            "disperse",
+            &"1.2.3".parse().unwrap(),
+            NaiveDate::from_ymd_opt(2024, 1, 5).unwrap(),
+        ));
+        assert_eq!(lines, vec![b".TH DISPERSE 1\n".to_vec()]);
+    }
+
+    #[test]
+    fn test_update_os_other() {
+        assert_eq!(
+            update(".Dd March 3, 2019\n.Os Linux 5.10\n"),
+            ".Dd January 5, 2024\n.Os Linux 5.10\n"
+        );
+        assert_eq!(
+            update(".Dd March 3, 2019\n.Os \"Linux 5.10\"\n"),
+            ".Dd January 5, 2024\n.Os \"Linux 5.10\"\n"
+        );
+    }
+
+    fn update_format(format: super::Format, text: &str) -> Option<String> {
+        let mut lines = text
+            .split_inclusive('\n')
+            .map(|l| l.as_bytes().to_vec())
+            .collect::<Vec<_>>();
+        if super::update_lines(
+            format,
+            &mut lines,
+            "disperse",
+            &"1.2.3".parse().unwrap(),
+            NaiveDate::from_ymd_opt(2024, 1, 5).unwrap(),
+        ) {
+            Some(String::from_utf8(lines.concat()).unwrap())
+        } else {
+            None
+        }
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        use super::Format;
+        use std::path::Path;
+        assert_eq!(Format::from_path(Path::new("man/foo.1")), Format::Roff);
+        assert_eq!(Format::from_path(Path::new("foo.1.scd")), Format::Scdoc);
+        assert_eq!(Format::from_path(Path::new("foo.adoc")), Format::AsciiDoc);
+        assert_eq!(
+            Format::from_path(Path::new("foo.1.md")),
+            Format::PandocMarkdown
+        );
+        assert_eq!(Format::from_path(Path::new("foo.1.ronn")), Format::Ronn);
+    }
+
+    #[test]
+    fn test_update_scdoc() {
+        assert_eq!(
+            update_format(
+                super::Format::Scdoc,
+                "disperse(1) \"disperse 0.1.0\" \"General Commands\"\n\n# NAME\n"
+            )
+            .unwrap(),
+            "disperse(1) \"disperse 1.2.3\" \"General Commands\"\n\n# NAME\n"
+        );
+        assert_eq!(
+            update_format(super::Format::Scdoc, "disperse(1)\n\n# NAME\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_update_asciidoc() {
+        assert_eq!(
+            update_format(
+                super::Format::AsciiDoc,
+                "= disperse(1)\n:doctype: manpage\n:revnumber: 0.1.0\n:revdate: 2019-12-31\n:mansource: Disperse 0.1.0\n"
+            )
+            .unwrap(),
+            "= disperse(1)\n:doctype: manpage\n:revnumber: 1.2.3\n:revdate: 2024-01-05\n:mansource: Disperse 1.2.3\n"
+        );
+        assert_eq!(
+            update_format(super::Format::AsciiDoc, "= disperse(1)\n"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_update_pandoc() {
+        assert_eq!(
+            update_format(
+                super::Format::PandocMarkdown,
+                "% DISPERSE(1) Version 0.1.0 | User Manual\n% Jelmer\n% December 2019\n\n# NAME\n"
+            )
+            .unwrap(),
+            "% DISPERSE(1) Version 1.2.3 | User Manual\n% Jelmer\n% January 2024\n\n# NAME\n"
+        );
+        assert_eq!(
+            update_format(
+                super::Format::PandocMarkdown,
+                "---\ntitle: DISPERSE(1)\nfooter: disperse 0.1.0\ndate: \"2019-12-31\"\n---\n\n# NAME\n"
+            )
+            .unwrap(),
+            "---\ntitle: DISPERSE(1)\nfooter: disperse 1.2.3\ndate: \"2024-01-05\"\n---\n\n# NAME\n"
+        );
+    }
+
+    #[test]
+    fn test_update_ronn() {
+        assert_eq!(
+            update_format(
+                super::Format::Ronn,
+                "organization: disperse 0.1.0\ndate: 2019-12-31\n"
+            )
+            .unwrap(),
+            "organization: disperse 1.2.3\ndate: 2024-01-05\n"
+        );
+        assert_eq!(
+            update_format(
+                super::Format::Ronn,
+                "---\nmanual: disperse 0.1.0\n---\ndisperse(1) -- release software\n=====\n"
+            )
+            .unwrap(),
+            "---\nmanual: disperse 1.2.3\n---\ndisperse(1) -- release software\n=====\n"
+        );
+        assert_eq!(
+            update_format(
+                super::Format::Ronn,
+                "disperse(1) -- release software\n=====\n"
+            ),
+            None
+        );
+        assert_eq!(
+            update_format(
+                super::Format::Ronn,
+                "disperse(1) -- release software\n=====\n\n## EXAMPLES\n\ndate: 2019-12-31\n"
+            ),
+            None
+        );
+    }
 }
@@ -17,12 +17,24 @@ pub struct ProjectConfig {
     #[serde(default, rename = "update-manpage")]
     pub update_manpages: Option<Vec<PathBuf>>,
 
+    /// Files in which to update copyright years on release.
+    #[serde(default, rename = "update-copyright")]
+    pub update_copyright: Option<Vec<PathBuf>>,
+
+    /// Holder of the copyright notices to update; defaults to the first
+    /// author of the project.
+    #[serde(default, rename = "copyright-holder")]
+    pub copyright_holder: Option<String>,
+
     #[serde(default)]
     pub launchpad: Option<Launchpad>,
 
     #[serde(default)]
     pub github: Option<GitHub>,
 
+    #[serde(default, rename = "github-release")]
+    pub github_release: Option<GitHubRelease>,
+
     #[serde(default, rename = "news-file")]
     pub news_file: Option<PathBuf>,
 
@@ -32,9 +44,26 @@ pub struct ProjectConfig {
     #[serde(default, rename = "verify-command")]
     pub verify_command: Option<String>,
 
+    /// Cargo registry to publish to, as named in `[registries]` in the cargo
+    /// configuration. Defaults to crates.io.
+    #[serde(default, rename = "cargo-registry")]
+    pub cargo_registry: Option<String>,
+
     #[serde(default, rename = "twine-upload")]
     pub twine_upload: Option<bool>,
 
+    /// Python interpreter used to build distributions.
+    #[serde(default)]
+    pub python: Option<String>,
+
+    /// Tool used to build binary wheels, for packages that are not pure Python.
+    #[serde(default, rename = "binary-wheels")]
+    pub binary_wheels: Option<WheelBuilder>,
+
+    /// Check that the sdist contains all versioned files.
+    #[serde(default, rename = "sdist-check")]
+    pub sdist_check: Option<SdistCheck>,
+
     #[serde(
         default,
         rename = "tarball-location",
@@ -42,11 +71,62 @@ pub struct ProjectConfig {
     )]
     pub tarball_location: Vec<String>,
 
+    /// Globs for additional files to attach to GitHub releases, relative to
+    /// the root of the tree.
+    #[serde(
+        default,
+        rename = "release-assets",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub release_assets: Vec<String>,
+
     #[serde(default, rename = "release-timeout")]
     pub release_timeout: Option<u64>,
 
+    /// How long to wait for CI to finish, in seconds.
     #[serde(default, rename = "ci-timeout")]
     pub ci_timeout: Option<u64>,
+
+    /// How CI results are judged before releasing.
+    #[serde(default)]
+    pub ci: Option<CIConfig>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Default)]
+pub struct CIConfig {
+    /// Only consider the checks that branch protection requires.
+    #[serde(default, rename = "required-only")]
+    pub required_only: bool,
+
+    /// Check run conclusions that count as passing, besides `success` and
+    /// `skipped`; e.g. `neutral` or `cancelled`.
+    #[serde(
+        default,
+        rename = "passing-conclusions",
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub passing_conclusions: Vec<String>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Default)]
+pub struct SdistCheck {
+    /// Fail the release rather than warn when files are missing.
+    #[serde(default)]
+    pub strict: bool,
+
+    /// Globs for versioned files that are not expected in the sdist.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ignore: Vec<String>,
+}
+
+/// Tools that can build binary wheels.
+#[derive(serde::Deserialize, serde::Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum WheelBuilder {
+    /// Build manylinux and musllinux wheels with cibuildwheel.
+    Cibuildwheel,
+    /// Build wheels for pyo3 extensions with maturin.
+    Maturin,
 }
 
 #[derive(serde::Deserialize, serde::Serialize)]
@@ -55,6 +135,19 @@ pub struct GitHub {
     pub branch: Option<String>,
 }
 
+#[derive(serde::Deserialize, serde::Serialize, Default)]
+pub struct GitHubRelease {
+    /// Create the release as a draft, and only publish it once all assets
+    /// have been uploaded.
+    #[serde(default)]
+    pub draft: bool,
+
+    /// Mark every release as a pre-release. Releases of pre-release versions,
+    /// such as "1.0rc1", are always marked as pre-releases.
+    #[serde(default)]
+    pub prerelease: bool,
+}
+
 #[derive(serde::Deserialize, serde::Serialize)]
 pub struct Launchpad {
     pub project: String,
@@ -63,10 +156,107 @@ pub struct Launchpad {
 
 #[derive(serde::Deserialize, serde::Serialize)]
 pub struct UpdateVersion {
+    /// File to update; it has to be valid UTF-8.
     pub path: std::path::PathBuf,
+    /// Regular expression for the lines to replace; derived from `new_line`
+    /// if unset.
     pub r#match: Option<String>,
     #[serde(rename = "new-line")]
     pub new_line: String,
+
+    /// Which of the matches to update: "first", "all" or a 1-based index.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub occurrences: Option<Occurrences>,
+
+    /// Match `match` against the whole file rather than each line, so that
+    /// it can span several lines. `^` and `$` still match at line breaks.
+    #[serde(rename = "multi-line", default)]
+    pub multi_line: bool,
+}
+
+/// Selects which matches of an `update_version` pattern are used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Occurrences {
+    /// Every match.
+    #[default]
+    All,
+
+    /// Only the first match.
+    First,
+
+    /// Only the Nth match, counting from 1.
+    Nth(usize),
+}
+
+impl Occurrences {
+    /// Select the matching items from a list of matches.
+    pub fn select<T>(&self, items: Vec<T>) -> Vec<T> {
+        match self {
+            Occurrences::All => items,
+            Occurrences::First => items.into_iter().take(1).collect(),
+            Occurrences::Nth(n) => items.into_iter().skip(n - 1).take(1).collect(),
+        }
+    }
+
+    /// The minimum number of matches needed for this selection to succeed.
+    pub fn required_matches(&self) -> usize {
+        match self {
+            Occurrences::All | Occurrences::First => 1,
+            Occurrences::Nth(n) => *n,
+        }
+    }
+}
+
+impl std::fmt::Display for Occurrences {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Occurrences::All => write!(f, "all"),
+            Occurrences::First => write!(f, "first"),
+            Occurrences::Nth(n) => write!(f, "{}", n),
+        }
+    }
+}
+
+impl std::str::FromStr for Occurrences {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(Occurrences::All),
+            "first" => Ok(Occurrences::First),
+            s => match s.parse::<usize>() {
+                Ok(0) => Err("occurrences are numbered from 1".to_string()),
+                Ok(n) => Ok(Occurrences::Nth(n)),
+                Err(_) => Err(format!("invalid occurrences: {}", s)),
+            },
+        }
+    }
+}
+
+impl serde::Serialize for Occurrences {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Occurrences::Nth(n) => serializer.serialize_u64(*n as u64),
+            o => serializer.serialize_str(&o.to_string()),
+        }
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Occurrences {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(serde::Deserialize)]
+        #[serde(untagged)]
+        enum Repr {
+            Index(u64),
+            Name(String),
+        }
+
+        match Repr::deserialize(deserializer)? {
+            Repr::Index(n) => n.to_string().parse(),
+            Repr::Name(s) => s.parse(),
+        }
+        .map_err(serde::de::Error::custom)
+    }
 }
 
 impl From<config::UpdateVersion> for UpdateVersion {
@@ -75,6 +265,8 @@ impl From<config::UpdateVersion> for UpdateVersion {
             path: u.path.unwrap().into(),
             r#match: u.match_,
             new_line: u.new_line.unwrap(),
+            occurrences: None,
+            multi_line: false,
         }
     }
 }
@@ -99,6 +291,7 @@ impl From<config::Project> for ProjectConfig {
                 url: p.github_url.clone().unwrap(),
                 branch: p.github_branch.clone(),
             }),
+            github_release: None,
             news_file: p.news_file.clone().map(|n| n.into()),
             update_manpages: {
                 let mps: Vec<_> = p.update_manpages.into_iter().map(|u| u.into()).collect();
@@ -108,13 +301,21 @@ impl From<config::Project> for ProjectConfig {
                     Some(mps)
                 }
             },
+            update_copyright: None,
+            copyright_holder: None,
             tag_name: p.tag_name.clone(),
             pre_dist_command: p.pre_dist_command.clone(),
             verify_command: p.verify_command.clone(),
             twine_upload: p.skip_twine_upload.map(|t| !t),
+            cargo_registry: None,
+            python: None,
+            binary_wheels: None,
+            sdist_check: None,
             tarball_location: p.tarball_location.clone(),
+            release_assets: vec![],
             release_timeout: p.timeout_days.map(|t| t as u64),
             ci_timeout: p.ci_timeout.map(|t| t as u64),
+            ci: None,
         }
     }
 }
@@ -162,3 +363,75 @@ pub fn read_project_with_fallback(tree: &dyn Tree) -> Result<ProjectConfig, BrzE
 
     Ok(read_old_project(&mut old_config).into())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_binary_wheels_from_toml() {
+        let cfg: ProjectConfig = toml::from_str("binary-wheels = \"maturin\"\n").unwrap();
+        assert_eq!(cfg.binary_wheels, Some(WheelBuilder::Maturin));
+        let cfg: ProjectConfig = toml::from_str("").unwrap();
+        assert_eq!(cfg.binary_wheels, None);
+        assert!(toml::from_str::<ProjectConfig>("binary-wheels = \"setup.py\"\n").is_err());
+    }
+
+    #[test]
+    fn test_github_release_from_toml() {
+        let cfg: ProjectConfig = toml::from_str("[github-release]\ndraft = true\n").unwrap();
+        let release = cfg.github_release.unwrap();
+        assert!(release.draft);
+        assert!(!release.prerelease);
+    }
+
+    #[test]
+    fn test_occurrences_from_toml() {
+        let cfg: ProjectConfig = toml::from_str(
+            r#"
+[[update_version]]
+path = "a"
+new-line = "$VERSION"
+
+[[update_version]]
+path = "b"
+new-line = "$VERSION"
+occurrences = "first"
+
+[[update_version]]
+path = "c"
+new-line = "$VERSION"
+occurrences = 2
+"#,
+        )
+        .unwrap();
+        let occurrences = cfg
+            .update_version
+            .unwrap()
+            .iter()
+            .map(|u| u.occurrences)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            occurrences,
+            vec![None, Some(Occurrences::First), Some(Occurrences::Nth(2))]
+        );
+    }
+
+    #[test]
+    fn test_occurrences_invalid() {
+        assert!("0".parse::<Occurrences>().is_err());
+        assert!("some".parse::<Occurrences>().is_err());
+        assert!(toml::from_str::<UpdateVersion>(
+            "path = \"a\"\nnew-line = \"$VERSION\"\noccurrences = 0\n"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_occurrences_select() {
+        assert_eq!(Occurrences::All.select(vec![1, 2, 3]), vec![1, 2, 3]);
+        assert_eq!(Occurrences::First.select(vec![1, 2, 3]), vec![1]);
+        assert_eq!(Occurrences::Nth(3).select(vec![1, 2, 3]), vec![3]);
+        assert_eq!(Occurrences::Nth(4).select(vec![1, 2, 3]), Vec::<i32>::new());
+    }
+}
@@ -0,0 +1,869 @@
+use blake2::digest::consts::U32;
+use blake2::{Blake2b, Digest};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::io::Read;
+use std::path::Path;
+use url::Url;
+
+/// The upload endpoint of the main PyPI instance.
+pub const DEFAULT_REPOSITORY_URL: &str = "https://upload.pypi.org/legacy/";
+
+/// The main PyPI index, used to check for files that were already uploaded.
+pub const DEFAULT_INDEX_URL: &str = "https://pypi.org/";
+
+#[derive(Debug)]
+pub enum Error {
+    IoError(std::io::Error),
+    /// The distribution could not be read, or lacks metadata.
+    InvalidDistribution(String),
+    /// No credentials were found for the repository.
+    MissingCredentials(String),
+    /// The repository already has a file with this name.
+    FileExists(String),
+    /// The repository rejected the metadata of the distribution.
+    InvalidMetadata(String),
+    /// The repository rejected the credentials.
+    AuthFailed(String),
+    HttpError(reqwest::Error),
+    /// Any other error response from the repository.
+    UploadFailed {
+        status: u16,
+        reason: String,
+    },
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::IoError(e)
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::HttpError(e)
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match &self {
+            Error::IoError(e) => write!(f, "IO error: {}", e),
+            Error::InvalidDistribution(e) => write!(f, "Invalid distribution: {}", e),
+            Error::MissingCredentials(url) => write!(f, "No credentials found for {}", url),
+            Error::FileExists(e) => write!(f, "File already exists: {}", e),
+            Error::InvalidMetadata(e) => write!(f, "Invalid metadata: {}", e),
+            Error::AuthFailed(e) => write!(f, "Authentication failed: {}", e),
+            Error::HttpError(e) => write!(f, "HTTP error: {}", e),
+            Error::UploadFailed { status, reason } => {
+                write!(f, "Upload failed with status {}: {}", status, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Parse core metadata (METADATA or PKG-INFO) into a list of fields.
+///
+/// Fields that can occur multiple times are returned once per occurrence. A
+/// message body, if present, is returned as the "Description" field.
+pub fn parse_metadata(text: &str) -> Vec<(String, String)> {
+    let mut fields: Vec<(String, String)> = vec![];
+    let mut lines = text.split_inclusive('\n');
+    for line in lines.by_ref() {
+        let line = line.trim_end_matches(['\r', '\n']);
+        if line.is_empty() {
+            break;
+        }
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = fields.last_mut() {
+                value.push('\n');
+                value.push_str(line.trim_start_matches([' ', '\t', '|']));
+            }
+            continue;
+        }
+        if let Some((key, value)) = line.split_once(':') {
+            fields.push((key.trim().to_string(), value.trim().to_string()));
+        }
+    }
+    let body = lines.collect::<String>();
+    if !body.trim().is_empty() {
+        fields.retain(|(k, _)| !k.eq_ignore_ascii_case("description"));
+        fields.push(("Description".to_string(), body));
+    }
+    fields
+}
+
+/// Return the name of the form field used by the upload API for a metadata field.
+fn form_field_name(key: &str) -> String {
+    match key.to_ascii_lowercase().as_str() {
+        "classifier" => "classifiers".to_string(),
+        "project-url" => "project_urls".to_string(),
+        k => k.replace('-', "_"),
+    }
+}
+
+/// The components of a wheel filename, as described in PEP 427.
+#[derive(Debug, PartialEq, Eq)]
+pub struct WheelFilename {
+    pub name: String,
+    pub version: String,
+    pub build: Option<String>,
+    pub python: String,
+    pub abi: String,
+    pub platform: String,
+}
+
+impl WheelFilename {
+    /// Parse a filename of the form
+    /// `{name}-{version}(-{build})?-{python}-{abi}-{platform}.whl`.
+    pub fn parse(filename: &str) -> Option<Self> {
+        let stem = filename.strip_suffix(".whl")?;
+        let parts = stem.split('-').collect::<Vec<_>>();
+        let (name, version, build, tags) = match parts.as_slice() {
+            [name, version, tags @ ..] if tags.len() == 3 => (name, version, None, tags),
+            [name, version, build, tags @ ..] if tags.len() == 3 => {
+                (name, version, Some(build.to_string()), tags)
+            }
+            _ => return None,
+        };
+        Some(Self {
+            name: name.to_string(),
+            version: version.to_string(),
+            build,
+            python: tags[0].to_string(),
+            abi: tags[1].to_string(),
+            platform: tags[2].to_string(),
+        })
+    }
+
+    /// Whether the wheel contains only pure Python code.
+    pub fn is_pure(&self) -> bool {
+        self.abi == "none" && self.platform == "any"
+    }
+}
+
+/// A Python distribution (wheel or sdist) ready to be uploaded.
+#[derive(Debug)]
+pub struct Distribution {
+    pub filename: String,
+    pub filetype: &'static str,
+    pub pyversion: String,
+    pub metadata: Vec<(String, String)>,
+    pub content: Vec<u8>,
+}
+
+impl Distribution {
+    /// Look up the first occurrence of a metadata field.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.metadata
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v.as_str())
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.get("Name")
+    }
+
+    pub fn version(&self) -> Option<&str> {
+        self.get("Version")
+    }
+
+    pub fn sha256_digest(&self) -> String {
+        format!("{:x}", Sha256::digest(&self.content))
+    }
+
+    pub fn blake2_256_digest(&self) -> String {
+        format!("{:x}", Blake2b::<U32>::digest(&self.content))
+    }
+
+    /// Open a wheel or sdist and extract its metadata.
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        let filename = path
+            .file_name()
+            .and_then(|f| f.to_str())
+            .ok_or_else(|| Error::InvalidDistribution(path.display().to_string()))?
+            .to_string();
+        let content = std::fs::read(path)?;
+        Self::from_bytes(filename, content)
+    }
+
+    pub fn from_bytes(filename: String, content: Vec<u8>) -> Result<Self, Error> {
+        let (filetype, pyversion, metadata) = if filename.ends_with(".whl") {
+            let wheel = WheelFilename::parse(&filename).ok_or_else(|| {
+                Error::InvalidDistribution(format!("invalid wheel filename: {}", filename))
+            })?;
+            let metadata = read_zip_member(&content, |name| {
+                let mut parts = name.split('/');
+                matches!(
+                    (parts.next(), parts.next(), parts.next()),
+                    (Some(dir), Some("METADATA"), None) if dir.ends_with(".dist-info")
+                )
+            })?;
+            ("bdist_wheel", wheel.python, metadata)
+        } else if filename.ends_with(".tar.gz") {
+            let metadata = read_tar_gz_member(&content, is_sdist_pkg_info)?;
+            ("sdist", "source".to_string(), metadata)
+        } else if filename.ends_with(".zip") {
+            let metadata = read_zip_member(&content, is_sdist_pkg_info)?;
+            ("sdist", "source".to_string(), metadata)
+        } else {
+            return Err(Error::InvalidDistribution(format!(
+                "unknown distribution type: {}",
+                filename
+            )));
+        };
+
+        let metadata = metadata.ok_or_else(|| {
+            Error::InvalidDistribution(format!("no metadata found in {}", filename))
+        })?;
+
+        Ok(Self {
+            filename,
+            filetype,
+            pyversion,
+            metadata: parse_metadata(&String::from_utf8_lossy(&metadata)),
+            content,
+        })
+    }
+
+    /// Return the form fields to send along with the file.
+    pub fn form_fields(&self) -> Vec<(String, String)> {
+        let mut fields = vec![
+            (":action".to_string(), "file_upload".to_string()),
+            ("protocol_version".to_string(), "1".to_string()),
+            ("filetype".to_string(), self.filetype.to_string()),
+            ("pyversion".to_string(), self.pyversion.clone()),
+            ("sha256_digest".to_string(), self.sha256_digest()),
+            ("blake2_256_digest".to_string(), self.blake2_256_digest()),
+        ];
+        fields.extend(
+            self.metadata
+                .iter()
+                .map(|(k, v)| (form_field_name(k), v.clone())),
+        );
+        fields
+    }
+}
+
+fn is_sdist_pkg_info(name: &str) -> bool {
+    let mut parts = name.split('/');
+    matches!(
+        (parts.next(), parts.next(), parts.next()),
+        (Some(_), Some("PKG-INFO"), None)
+    )
+}
+
+fn read_zip_member(
+    content: &[u8],
+    matches: impl Fn(&str) -> bool,
+) -> Result<Option<Vec<u8>>, Error> {
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(content))
+        .map_err(|e| Error::InvalidDistribution(e.to_string()))?;
+    for i in 0..archive.len() {
+        let mut file = archive
+            .by_index(i)
+            .map_err(|e| Error::InvalidDistribution(e.to_string()))?;
+        if matches(file.name()) {
+            let mut ret = vec![];
+            file.read_to_end(&mut ret)?;
+            return Ok(Some(ret));
+        }
+    }
+    Ok(None)
+}
+
+fn read_tar_gz_member(
+    content: &[u8],
+    matches: impl Fn(&str) -> bool,
+) -> Result<Option<Vec<u8>>, Error> {
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(content));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.to_string_lossy().into_owned();
+        if matches(path.trim_start_matches("./")) {
+            let mut ret = vec![];
+            entry.read_to_end(&mut ret)?;
+            return Ok(Some(ret));
+        }
+    }
+    Ok(None)
+}
+
+/// Find the API token for a repository.
+///
+/// The token is read from the `TWINE_PASSWORD` environment variable or,
+/// failing that, from the keyring entry for the repository URL and the
+/// `__token__` user, which is where twine and keyring store it as well.
+pub fn find_api_token(repository_url: &str) -> Result<String, Error> {
+    if let Ok(token) = std::env::var("TWINE_PASSWORD") {
+        return Ok(token);
+    }
+    let entry = keyring::Entry::new(repository_url, "__token__")
+        .map_err(|e| Error::MissingCredentials(format!("{}: {}", repository_url, e)))?;
+    match entry.get_password() {
+        Ok(token) => Ok(token),
+        Err(keyring::Error::NoEntry) => Err(Error::MissingCredentials(repository_url.to_string())),
+        Err(e) => Err(Error::MissingCredentials(format!(
+            "{}: {}",
+            repository_url, e
+        ))),
+    }
+}
+
+/// Map an error response from the upload API to an error.
+fn upload_error(status: u16, reason: &str, filename: &str) -> Error {
+    let lower = reason.to_ascii_lowercase();
+    match status {
+        409 => Error::FileExists(filename.to_string()),
+        400 if lower.contains("already exists") || lower.contains("file exists") => {
+            Error::FileExists(filename.to_string())
+        }
+        400 => Error::InvalidMetadata(reason.to_string()),
+        401 | 403 => Error::AuthFailed(reason.to_string()),
+        _ => Error::UploadFailed {
+            status,
+            reason: reason.to_string(),
+        },
+    }
+}
+
+/// Upload a distribution using the legacy upload API.
+pub async fn upload_distribution(
+    client: &reqwest::Client,
+    repository_url: &str,
+    token: &str,
+    dist: &Distribution,
+) -> Result<(), Error> {
+    let mut form = reqwest::multipart::Form::new();
+    for (key, value) in dist.form_fields() {
+        form = form.text(key, value);
+    }
+    form = form.part(
+        "content",
+        reqwest::multipart::Part::bytes(dist.content.clone())
+            .file_name(dist.filename.clone())
+            .mime_str("application/octet-stream")?,
+    );
+
+    let response = client
+        .post(repository_url)
+        .basic_auth("__token__", Some(token))
+        .multipart(form)
+        .send()
+        .await?;
+
+    let status = response.status();
+    if status.is_success() {
+        return Ok(());
+    }
+    // Warehouse reports the reason in the status line rather than the body.
+    let reason = status.canonical_reason().unwrap_or_default().to_string();
+    let body = response.text().await.unwrap_or_default();
+    let reason = if body.trim().is_empty() {
+        reason
+    } else {
+        body.trim().to_string()
+    };
+    Err(upload_error(status.as_u16(), &reason, &dist.filename))
+}
+
+/// Metadata versions that PyPI accepts.
+const SUPPORTED_METADATA_VERSIONS: &[&str] =
+    &["1.0", "1.1", "1.2", "2.0", "2.1", "2.2", "2.3", "2.4"];
+
+/// Compare two versions, ignoring trailing zero components of the release
+/// segment (so that "1.0" and "1.0.0" are considered equal).
+fn versions_equal(a: &str, b: &str) -> bool {
+    fn normalize(v: &str) -> Vec<&str> {
+        let mut parts = v
+            .trim()
+            .trim_start_matches('v')
+            .split('.')
+            .collect::<Vec<_>>();
+        while parts.len() > 1
+            && parts
+                .last()
+                .is_some_and(|p| p.trim_start_matches('0').is_empty())
+        {
+            parts.pop();
+        }
+        parts
+    }
+    normalize(a) == normalize(b)
+}
+
+/// Try to render a reStructuredText description with readme_renderer, as
+/// PyPI does.
+///
+/// The renderer runs in a separate Python interpreter, so that it uses the
+/// same environment as the build.
+///
+/// Returns `None` if readme_renderer is not available.
+fn renders_as_rst(text: &str, python: Option<&str>) -> Option<bool> {
+    use std::io::Write;
+    const SCRIPT: &str = r#"
+import sys
+try:
+    from readme_renderer.rst import render
+except ImportError:
+    sys.exit(2)
+sys.exit(0 if render(sys.stdin.read()) is not None else 1)
+"#;
+    let python = python.unwrap_or(crate::python::DEFAULT_PYTHON);
+    log::debug!("Checking long description with {} readme_renderer", python);
+    let mut child = std::process::Command::new(python)
+        .arg("-c")
+        .arg(SCRIPT)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .ok()?;
+    // Ignore write errors; the interpreter may exit early if
+    // readme_renderer is missing.
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = stdin.write_all(text.as_bytes());
+    }
+    let status = child.wait().ok()?;
+    match status.code() {
+        Some(0) => Some(true),
+        Some(1) => Some(false),
+        _ => None,
+    }
+}
+
+/// Check a distribution for problems that would make PyPI reject it, or
+/// render its description incorrectly. This is similar to `twine check`.
+///
+/// Returns a list of problems; warnings are logged. `python` is the
+/// interpreter used to check that a reStructuredText description renders.
+pub fn check_distribution(
+    dist: &Distribution,
+    expected_version: &str,
+    python: Option<&str>,
+) -> Vec<String> {
+    let mut problems = vec![];
+
+    match dist.get("Metadata-Version") {
+        None => problems.push("missing Metadata-Version".to_string()),
+        Some(v) if !SUPPORTED_METADATA_VERSIONS.contains(&v) => {
+            problems.push(format!("unsupported Metadata-Version {}", v))
+        }
+        Some(_) => {}
+    }
+
+    for field in ["Name", "Version"] {
+        if dist.get(field).is_none_or(|v| v.trim().is_empty()) {
+            problems.push(format!("missing required field {}", field));
+        }
+    }
+
+    if let Some(version) = dist.version() {
+        if !versions_equal(version, expected_version) {
+            problems.push(format!(
+                "version {} does not match release version {}",
+                version, expected_version
+            ));
+        }
+    }
+
+    if let Some(wheel) = WheelFilename::parse(&dist.filename) {
+        if !versions_equal(&wheel.version, expected_version) {
+            problems.push(format!(
+                "filename version {} does not match release version {}",
+                wheel.version, expected_version
+            ));
+        }
+    }
+
+    let content_type = dist.get("Description-Content-Type").map(|c| {
+        c.split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase()
+    });
+    match dist.get("Description") {
+        None => log::warn!("{}: no long description", dist.filename),
+        Some(description) => match content_type.as_deref() {
+            Some("text/markdown") | Some("text/plain") => {}
+            None | Some("text/x-rst") => {
+                if content_type.is_none() {
+                    log::warn!(
+                        "{}: no Description-Content-Type, assuming text/x-rst",
+                        dist.filename
+                    );
+                }
+                match renders_as_rst(description, python) {
+                    Some(true) => {}
+                    Some(false) => problems.push(
+                        "long description is not valid reStructuredText and will not render on PyPI"
+                            .to_string(),
+                    ),
+                    None => log::warn!(
+                        "readme_renderer not available; not checking that the description renders"
+                    ),
+                }
+            }
+            Some(other) => problems.push(format!("unsupported Description-Content-Type {}", other)),
+        },
+    }
+
+    problems
+}
+
+/// Parse the JSON API response for a release into a map from filename to
+/// SHA256 digest.
+fn parse_release_files(data: &serde_json::Value) -> HashMap<String, String> {
+    data["urls"]
+        .as_array()
+        .map(|urls| {
+            urls.iter()
+                .filter_map(|u| {
+                    Some((
+                        u["filename"].as_str()?.to_string(),
+                        u["digests"]["sha256"]
+                            .as_str()
+                            .unwrap_or_default()
+                            .to_string(),
+                    ))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Return the files that have already been published for a release, along
+/// with their SHA256 digests.
+pub async fn published_files(
+    client: &reqwest::Client,
+    index_url: &Url,
+    name: &str,
+    version: &str,
+) -> Result<HashMap<String, String>, Error> {
+    let url = index_url
+        .join(&format!("pypi/{}/{}/json", name, version))
+        .map_err(|e| Error::InvalidDistribution(format!("{}: {}", name, e)))?;
+    let response = client.get(url).send().await?;
+    if response.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(HashMap::new());
+    }
+    let data: serde_json::Value = response.error_for_status()?.json().await?;
+    Ok(parse_release_files(&data))
+}
+
+/// Upload a set of Python distributions to a package repository.
+///
+/// Files that are already present in the index with the same contents are
+/// skipped, so that uploading can be retried after a partial failure.
+pub async fn upload_python_artifacts(
+    repository_url: Option<&str>,
+    index_url: Option<&Url>,
+    pypi_paths: &[&Path],
+) -> Result<(), Error> {
+    let repository_url = repository_url.unwrap_or(DEFAULT_REPOSITORY_URL);
+    let default_index_url = Url::parse(DEFAULT_INDEX_URL).unwrap();
+    let index_url = index_url.unwrap_or(&default_index_url);
+    let dists = pypi_paths
+        .iter()
+        .map(|p| Distribution::open(p))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let client = reqwest::ClientBuilder::new()
+        .user_agent(crate::USER_AGENT)
+        .build()?;
+
+    let mut published: HashMap<(String, String), HashMap<String, String>> = HashMap::new();
+    let mut token = None;
+    for dist in dists {
+        let (Some(name), Some(version)) = (dist.name(), dist.version()) else {
+            return Err(Error::InvalidMetadata(format!(
+                "{} lacks a name or version",
+                dist.filename
+            )));
+        };
+        let key = (name.to_string(), version.to_string());
+        if !published.contains_key(&key) {
+            let files = published_files(&client, index_url, name, version).await?;
+            published.insert(key.clone(), files);
+        }
+        match published[&key].get(&dist.filename) {
+            Some(digest) if *digest == dist.sha256_digest() => {
+                log::info!("{} has already been uploaded, skipping", dist.filename);
+                continue;
+            }
+            Some(_) => {
+                return Err(Error::FileExists(format!(
+                    "{} (with different contents)",
+                    dist.filename
+                )));
+            }
+            None => {}
+        }
+        if token.is_none() {
+            token = Some(find_api_token(repository_url)?);
+        }
+        log::info!("Uploading {} to {}", dist.filename, repository_url);
+        upload_distribution(&client, repository_url, token.as_ref().unwrap(), &dist).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    const METADATA: &str = "Metadata-Version: 2.1
+Name: disperse
+Version: 0.1.0
+Summary: automation for creation of releases
+Classifier: Programming Language :: Python
+Classifier: Programming Language :: Rust
+Project-URL: Repository, https://github.com/jelmer/disperse
+Description-Content-Type: text/markdown
+
+# disperse
+
+Releases.
+";
+
+    #[test]
+    fn test_parse_metadata() {
+        let fields = parse_metadata(METADATA);
+        assert_eq!(
+            fields,
+            vec![
+                ("Metadata-Version".to_string(), "2.1".to_string()),
+                ("Name".to_string(), "disperse".to_string()),
+                ("Version".to_string(), "0.1.0".to_string()),
+                (
+                    "Summary".to_string(),
+                    "automation for creation of releases".to_string()
+                ),
+                (
+                    "Classifier".to_string(),
+                    "Programming Language :: Python".to_string()
+                ),
+                (
+                    "Classifier".to_string(),
+                    "Programming Language :: Rust".to_string()
+                ),
+                (
+                    "Project-URL".to_string(),
+                    "Repository, https://github.com/jelmer/disperse".to_string()
+                ),
+                (
+                    "Description-Content-Type".to_string(),
+                    "text/markdown".to_string()
+                ),
+                (
+                    "Description".to_string(),
+                    "# disperse\n\nReleases.\n".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_metadata_continuation() {
+        let fields = parse_metadata("Name: foo\nDescription: first\n        |second\n");
+        assert_eq!(
+            fields,
+            vec![
+                ("Name".to_string(), "foo".to_string()),
+                ("Description".to_string(), "first\nsecond".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_wheel_filename() {
+        let wheel = WheelFilename::parse("disperse-0.1.0-py3-none-any.whl").unwrap();
+        assert_eq!(wheel.name, "disperse");
+        assert_eq!(wheel.version, "0.1.0");
+        assert_eq!(wheel.build, None);
+        assert_eq!(wheel.python, "py3");
+        assert!(wheel.is_pure());
+
+        let wheel = WheelFilename::parse(
+            "dulwich-0.22.1-1-cp312-cp312-manylinux_2_17_x86_64.manylinux2014_x86_64.whl",
+        )
+        .unwrap();
+        assert_eq!(wheel.build.as_deref(), Some("1"));
+        assert_eq!(wheel.abi, "cp312");
+        assert!(!wheel.is_pure());
+
+        assert_eq!(WheelFilename::parse("disperse-0.1.0.tar.gz"), None);
+        assert_eq!(WheelFilename::parse("disperse-py3-none-any.whl"), None);
+    }
+
+    #[test]
+    fn test_form_field_name() {
+        assert_eq!(form_field_name("Metadata-Version"), "metadata_version");
+        assert_eq!(form_field_name("Classifier"), "classifiers");
+        assert_eq!(form_field_name("Project-URL"), "project_urls");
+        assert_eq!(form_field_name("Home-page"), "home_page");
+    }
+
+    #[test]
+    fn test_wheel() {
+        let mut buf = std::io::Cursor::new(vec![]);
+        let mut zip = zip::ZipWriter::new(&mut buf);
+        let options = zip::write::SimpleFileOptions::default();
+        zip.start_file("disperse/__init__.py", options).unwrap();
+        zip.start_file("disperse-0.1.0.dist-info/METADATA", options)
+            .unwrap();
+        zip.write_all(METADATA.as_bytes()).unwrap();
+        zip.finish().unwrap();
+
+        let dist = Distribution::from_bytes(
+            "disperse-0.1.0-py3-none-any.whl".to_string(),
+            buf.into_inner(),
+        )
+        .unwrap();
+        assert_eq!(dist.filetype, "bdist_wheel");
+        assert_eq!(dist.pyversion, "py3");
+        assert_eq!(dist.name(), Some("disperse"));
+        assert_eq!(dist.version(), Some("0.1.0"));
+        let fields = dist.form_fields();
+        assert!(fields.contains(&("metadata_version".to_string(), "2.1".to_string())));
+        assert!(fields.contains(&("sha256_digest".to_string(), dist.sha256_digest())));
+        assert_eq!(fields.iter().filter(|(k, _)| k == "classifiers").count(), 2);
+    }
+
+    #[test]
+    fn test_sdist() {
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+            vec![],
+            flate2::Compression::default(),
+        ));
+        let mut header = tar::Header::new_gnu();
+        header.set_size(METADATA.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder
+            .append_data(&mut header, "disperse-0.1.0/PKG-INFO", METADATA.as_bytes())
+            .unwrap();
+        let content = builder.into_inner().unwrap().finish().unwrap();
+
+        let dist = Distribution::from_bytes("disperse-0.1.0.tar.gz".to_string(), content).unwrap();
+        assert_eq!(dist.filetype, "sdist");
+        assert_eq!(dist.pyversion, "source");
+        assert_eq!(dist.name(), Some("disperse"));
+    }
+
+    #[test]
+    fn test_digests() {
+        let dist = Distribution {
+            filename: "foo-1.0.tar.gz".to_string(),
+            filetype: "sdist",
+            pyversion: "source".to_string(),
+            metadata: vec![],
+            content: b"abc".to_vec(),
+        };
+        assert_eq!(
+            dist.sha256_digest(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            dist.blake2_256_digest(),
+            "bddd813c634239723171ef3fee98579b94964e3bb1cb3e427262c8c068d52319"
+        );
+    }
+
+    #[test]
+    fn test_parse_release_files() {
+        let data = serde_json::json!({
+            "info": {"name": "disperse", "version": "0.1.0"},
+            "urls": [
+                {"filename": "disperse-0.1.0.tar.gz", "digests": {"sha256": "abcd"}},
+                {"filename": "disperse-0.1.0-py3-none-any.whl", "digests": {"sha256": "ef01"}},
+            ]
+        });
+        assert_eq!(
+            parse_release_files(&data),
+            maplit::hashmap! {
+                "disperse-0.1.0.tar.gz".to_string() => "abcd".to_string(),
+                "disperse-0.1.0-py3-none-any.whl".to_string() => "ef01".to_string(),
+            }
+        );
+        assert!(parse_release_files(&serde_json::json!({})).is_empty());
+    }
+
+    #[test]
+    fn test_versions_equal() {
+        assert!(versions_equal("1.0", "1.0.0"));
+        assert!(versions_equal("1.2.0", "1.2"));
+        assert!(!versions_equal("1.2.1", "1.2"));
+        assert!(!versions_equal("1.0.dev0", "1.0"));
+    }
+
+    fn dist(metadata: &str) -> Distribution {
+        Distribution {
+            filename: "disperse-0.1.0-py3-none-any.whl".to_string(),
+            filetype: "bdist_wheel",
+            pyversion: "py3".to_string(),
+            metadata: parse_metadata(metadata),
+            content: vec![],
+        }
+    }
+
+    #[test]
+    fn test_check_distribution() {
+        assert_eq!(
+            check_distribution(&dist(METADATA), "0.1.0", None),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn test_check_distribution_problems() {
+        assert_eq!(
+            check_distribution(&dist(METADATA), "0.2.0", None),
+            vec![
+                "version 0.1.0 does not match release version 0.2.0",
+                "filename version 0.1.0 does not match release version 0.2.0",
+            ]
+        );
+        assert_eq!(
+            check_distribution(
+                &dist("Metadata-Version: 3.0\nName: disperse\nDescription-Content-Type: text/html\n\nBody\n"),
+                "0.1.0",
+                None
+            ),
+            vec![
+                "unsupported Metadata-Version 3.0",
+                "missing required field Version",
+                "unsupported Description-Content-Type text/html",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_upload_error() {
+        assert!(matches!(
+            upload_error(400, "File already exists. See https://pypi.org/help/", "f"),
+            Error::FileExists(_)
+        ));
+        assert!(matches!(
+            upload_error(409, "Conflict", "f"),
+            Error::FileExists(_)
+        ));
+        assert!(matches!(
+            upload_error(400, "'' is an invalid value for Version.", "f"),
+            Error::InvalidMetadata(_)
+        ));
+        assert!(matches!(
+            upload_error(403, "Invalid API Token", "f"),
+            Error::AuthFailed(_)
+        ));
+        assert!(matches!(
+            upload_error(500, "Internal Server Error", "f"),
+            Error::UploadFailed { status: 500, .. }
+        ));
+    }
+}
@@ -1,18 +1,20 @@
+use crate::config::GitHubHostConfig;
+use crate::project_config::WheelBuilder;
 use crate::Version;
 use breezyshim::error::Error as BrzError;
-use breezyshim::tree::{Tree, WorkingTree};
+use breezyshim::tree::{MutableTree, Tree, WorkingTree};
+use futures::StreamExt;
 use pyo3::prelude::*;
 
 use serde_json::Value;
+use std::collections::HashMap;
 use std::path::Path;
-use std::process::Command;
 use std::str::FromStr;
 use url::Url;
-use xmlrpc::Request;
 
 #[derive(Debug)]
 pub enum Error {
-    BrzError(BrzError),
+    BrzError(Box<BrzError>),
     VersionError(String),
     IoError(std::io::Error),
     Other(String),
@@ -20,7 +22,7 @@ pub enum Error {
 
 impl From<BrzError> for Error {
     fn from(e: BrzError) -> Self {
-        Error::BrzError(e)
+        Error::BrzError(Box::new(e))
     }
 }
 
@@ -54,25 +56,32 @@ pub fn update_version_in_pyproject_toml(
         .parse()
         .map_err(|e| Error::Other(format!("Invalid TOML in pyproject.toml: {}", e)))?;
 
-    if let Some(project) = parsed_toml
+    let Some(project) = parsed_toml
         .as_table_mut()
         .get_mut("project")
         .and_then(|v| v.as_table_mut())
-    {
-        if let Some(dynamic) = project.get("dynamic").and_then(|v| v.as_array()) {
-            if dynamic.iter().any(|v| v.as_str() == Some("version")) {
-                return Ok(false);
-            }
-        }
+    else {
+        return Ok(false);
+    };
 
-        if !project.contains_key("version") {
-            log::warn!("No version in pyproject.toml");
+    if let Some(dynamic) = project.get("dynamic").and_then(|v| v.as_array()) {
+        if dynamic.iter().any(|v| v.as_str() == Some("version")) {
             return Ok(false);
         }
+    }
 
-        project["version"] = toml_edit::value(new_version.to_string());
+    if !project.contains_key("version") {
+        log::warn!("No version in pyproject.toml");
+        return Ok(false);
     }
 
+    project["version"] = toml_edit::value(new_version.to_string());
+
+    tree.put_file_bytes_non_atomic(
+        Path::new("pyproject.toml"),
+        parsed_toml.to_string().as_bytes(),
+    )?;
+
     Ok(true)
 }
 
@@ -94,68 +103,155 @@ pub fn find_version_in_pyproject_toml(tree: &dyn Tree) -> Result<Option<Version>
         .transpose()
 }
 
-pub fn pypi_discover_urls(pypi_user: &str) -> Result<Vec<url::Url>, Error> {
-    let request = Request::new("user_packages").arg(pypi_user);
+/// How long cached PyPI project metadata is used before it is fetched again.
+const PYPI_CACHE_TTL: std::time::Duration = std::time::Duration::from_secs(24 * 60 * 60);
+
+/// The maximum number of concurrent requests to PyPI.
+const PYPI_CONCURRENCY: usize = 8;
+
+/// Pick the URL of the source repository from the `project_urls` of a
+/// project's metadata.
+///
+/// Without an explicit `Repository` entry, the first URL of a repository on
+/// github.com or one of `github_hosts` is used.
+fn repository_url_from_project_urls(
+    project_urls: &serde_json::Map<String, Value>,
+    github_hosts: Option<&HashMap<String, GitHubHostConfig>>,
+) -> Option<Url> {
+    let urls = project_urls
+        .iter()
+        .filter_map(|(key, url)| Some((key, url.as_str()?)))
+        .filter(|(_, url)| *url != "UNKNOWN")
+        .collect::<Vec<_>>();
+
+    if let Some((_, url)) = urls.iter().find(|(key, _)| key.as_str() == "Repository") {
+        match Url::parse(url) {
+            Ok(url) => return Some(url),
+            Err(e) => log::warn!("Could not parse URL {}: {}", url, e),
+        }
+    }
 
-    let response = request
-        .call_url("https://pypi.org/pypi")
-        .map_err(|e| Error::Other(format!("Error calling PyPI: {}", e)))?;
+    urls.into_iter().find_map(|(_, url)| {
+        let parsed_url = Url::parse(url).ok()?;
+        if parsed_url
+            .host_str()
+            .is_some_and(|host| crate::github::is_github_host(host, github_hosts))
+            && parsed_url.path().trim_matches('/').matches('/').count() == 1
+        {
+            Some(parsed_url)
+        } else {
+            None
+        }
+    })
+}
 
-    let mut ret = vec![];
+fn pypi_cache_path(index_url: &Url, name: &str) -> Option<std::path::PathBuf> {
+    let xdg = xdg::BaseDirectories::with_prefix("disperse").ok()?;
+    let host = index_url.host_str().unwrap_or("local");
+    xdg.place_cache_file(format!("pypi/{}/{}.json", host, name))
+        .ok()
+}
 
-    let client = reqwest::blocking::ClientBuilder::new()
+/// Read cached project metadata, if it is fresh enough.
+fn read_pypi_cache(path: &Path) -> Option<Value> {
+    let age = std::fs::metadata(path)
+        .ok()?
+        .modified()
+        .ok()?
+        .elapsed()
+        .ok()?;
+    if age > PYPI_CACHE_TTL {
+        return None;
+    }
+    serde_json::from_slice(&std::fs::read(path).ok()?).ok()
+}
+
+async fn fetch_pypi_project(
+    client: &reqwest::Client,
+    index_url: &Url,
+    name: &str,
+) -> Result<Value, Error> {
+    let cache_path = pypi_cache_path(index_url, name);
+    if let Some(data) = cache_path.as_deref().and_then(read_pypi_cache) {
+        log::debug!("Using cached metadata for {}", name);
+        return Ok(data);
+    }
+
+    let url = index_url
+        .join(&format!("pypi/{}/json", name))
+        .map_err(|e| Error::Other(format!("Invalid index URL {}: {}", index_url, e)))?;
+    let data: Value = client
+        .get(url.clone())
+        .send()
+        .await
+        .and_then(|r| r.error_for_status())
+        .map_err(|e| Error::Other(format!("Error fetching {}: {}", url, e)))?
+        .json()
+        .await
+        .map_err(|e| Error::Other(format!("Error parsing JSON from {}: {}", url, e)))?;
+
+    if let Some(cache_path) = cache_path {
+        // Only keep the parts we need; the full response includes all releases.
+        let cached = serde_json::json!({"info": {"project_urls": data["info"]["project_urls"]}});
+        if let Err(e) = std::fs::write(&cache_path, cached.to_string()) {
+            log::debug!("Unable to write cache {}: {}", cache_path.display(), e);
+        }
+    }
+    Ok(data)
+}
+
+/// Find the repository URLs of a list of PyPI projects.
+///
+/// PyPI has no supported API for listing the projects of a user, so the
+/// projects have to be named explicitly. The metadata of each project is
+/// fetched from the JSON API at `<index>/pypi/<name>/json`.
+pub async fn pypi_project_urls(
+    projects: &[String],
+    index_url: Option<&Url>,
+    github_hosts: Option<&HashMap<String, GitHubHostConfig>>,
+) -> Result<Vec<url::Url>, Error> {
+    let default_index_url = Url::parse(crate::pypi::DEFAULT_INDEX_URL).unwrap();
+    let index_url = index_url.unwrap_or(&default_index_url);
+    project_repository_urls(&pypi_client()?, index_url, projects, github_hosts).await
+}
+
+fn pypi_client() -> Result<reqwest::Client, Error> {
+    reqwest::ClientBuilder::new()
         .user_agent(crate::USER_AGENT)
         .build()
-        .map_err(|e| Error::Other(format!("Error building HTTP client: {}", e)))?;
-
-    for package in response.as_array().unwrap().iter() {
-        let package_str = package.as_array().unwrap()[1].as_str().unwrap();
-
-        let req_url = format!("https://pypi.org/pypi/{}/json", package_str);
-        let resp = client
-            .get(&req_url)
-            .send()
-            .map_err(|e| Error::Other(format!("Error fetching {}: {}", req_url, e)))?;
-
-        let data: Value = resp
-            .json()
-            .map_err(|e| Error::Other(format!("Error parsing JSON from {}: {}", req_url, e)))?;
-        if let Some(project_urls) = data["info"]["project_urls"].as_object() {
-            if project_urls.is_empty() {
-                log::debug!("Project {} does not have project URLs", package_str);
+        .map_err(|e| Error::Other(format!("Error building HTTP client: {}", e)))
+}
+
+/// Fetch the metadata of each project from the JSON API and pick out its
+/// repository URL.
+async fn project_repository_urls(
+    client: &reqwest::Client,
+    index_url: &Url,
+    projects: &[String],
+    github_hosts: Option<&HashMap<String, GitHubHostConfig>>,
+) -> Result<Vec<url::Url>, Error> {
+    let results = futures::stream::iter(projects.iter())
+        .map(|name| async move { (name, fetch_pypi_project(client, index_url, name).await) })
+        .buffered(PYPI_CONCURRENCY)
+        .collect::<Vec<_>>()
+        .await;
+
+    let mut ret = vec![];
+    for (name, result) in results {
+        let data = match result {
+            Ok(data) => data,
+            Err(e) => {
+                log::warn!("Unable to fetch metadata for {}: {}", name, e);
                 continue;
             }
-
-            for (key, url) in project_urls.iter() {
-                if url == "UNKNOWN" {
-                    continue;
-                }
-                if key == "Repository" {
-                    ret.push(
-                        url.as_str().unwrap().parse().map_err(|e| {
-                            Error::Other(format!("Error parsing URL {}: {}", url, e))
-                        })?,
-                    );
-                    break;
-                }
-                let parsed_url = match Url::parse(url.as_str().unwrap()) {
-                    Ok(v) => v,
-                    Err(e) => {
-                        log::warn!("Could not parse URL {}: {}", url, e);
-                        continue;
-                    }
-                };
-                if parsed_url.host_str() == Some("github.com")
-                    && parsed_url.path().trim_matches('/').matches('/').count() == 1
-                {
-                    ret.push(
-                        url.as_str().unwrap().parse().map_err(|e| {
-                            Error::Other(format!("Error parsing URL {}: {}", url, e))
-                        })?,
-                    );
-                    break;
+        };
+        match data["info"]["project_urls"].as_object() {
+            Some(project_urls) if !project_urls.is_empty() => {
+                if let Some(url) = repository_url_from_project_urls(project_urls, github_hosts) {
+                    ret.push(url);
                 }
             }
+            _ => log::debug!("Project {} does not have project URLs", name),
         }
     }
 
@@ -215,17 +311,751 @@ pub fn find_hatch_vcs_version(tree: &WorkingTree) -> Option<Version> {
         return None;
     }
 
-    let output = String::from_utf8(output.stdout).unwrap();
+    let output = String::from_utf8(output.stdout).ok()?;
 
-    let parts = output.split('.').take(3).collect::<Vec<_>>();
+    // Development versions look like "0.1.dev3+g1234567"; only keep the
+    // numeric release segment.
+    let parts = output
+        .trim()
+        .split('.')
+        .take(3)
+        .map_while(|p| p.parse().ok())
+        .collect::<Vec<_>>();
 
     Some(Version {
-        major: parts[0].parse().unwrap(),
-        minor: parts.get(1).map(|v| v.parse().unwrap()),
-        micro: parts.get(2).map(|v| v.parse().unwrap()),
+        major: *parts.first()?,
+        minor: parts.get(1).copied(),
+        micro: parts.get(2).copied(),
     })
 }
 
+/// How a Python project declares its version in setup.cfg or in
+/// `[tool.setuptools.dynamic]`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum VersionSpec {
+    /// The version is given literally.
+    Literal(String),
+    /// `attr: package.module.attribute`
+    Attr(String),
+    /// `file: VERSION`
+    File(std::path::PathBuf),
+}
+
+impl std::str::FromStr for VersionSpec {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        Ok(if let Some(attr) = s.strip_prefix("attr:") {
+            VersionSpec::Attr(attr.trim().to_string())
+        } else if let Some(file) = s.strip_prefix("file:") {
+            VersionSpec::File(file.trim().into())
+        } else {
+            VersionSpec::Literal(s.to_string())
+        })
+    }
+}
+
+/// Find the span of the value of `key` in `section` of an INI-style file.
+fn find_ini_value(text: &str, section: &str, key: &str) -> Option<std::ops::Range<usize>> {
+    let mut current = None;
+    let mut offset = 0;
+    for line in text.split_inclusive('\n') {
+        let start = offset;
+        offset += line.len();
+        let trimmed = line.trim();
+        if let Some(name) = trimmed.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            current = Some(name.trim());
+            continue;
+        }
+        if current != Some(section) || line.starts_with([' ', '\t', '#', ';']) {
+            continue;
+        }
+        let Some(sep) = line.find(['=', ':']) else {
+            continue;
+        };
+        if line[..sep].trim() != key {
+            continue;
+        }
+        let value = &line[sep + 1..];
+        let value_start = start + sep + 1 + (value.len() - value.trim_start().len());
+        let value_end = start + sep + 1 + value.trim_end().len();
+        return Some(value_start..value_end.max(value_start));
+    }
+    None
+}
+
+/// Find the version declaration in the `[metadata]` section of setup.cfg.
+pub fn find_version_spec_in_setup_cfg(text: &str) -> Option<VersionSpec> {
+    let span = find_ini_value(text, "metadata", "version")?;
+    Some(text[span].parse().unwrap())
+}
+
+/// Find the version declaration in `[tool.setuptools.dynamic]` in pyproject.toml.
+pub fn find_version_spec_in_pyproject_toml(tree: &dyn Tree) -> Result<Option<VersionSpec>, Error> {
+    let content = match tree.get_file_text(Path::new("pyproject.toml")) {
+        Ok(v) => v,
+        Err(BrzError::NoSuchFile(_)) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    let parsed_toml: toml_edit::DocumentMut = String::from_utf8(content)
+        .map_err(|e| Error::Other(format!("Invalid UTF-8 in pyproject.toml: {}", e)))?
+        .parse()
+        .map_err(|e| Error::Other(format!("Invalid TOML in pyproject.toml: {}", e)))?;
+
+    let Some(version) = parsed_toml
+        .get("tool")
+        .and_then(|t| t.get("setuptools"))
+        .and_then(|t| t.get("dynamic"))
+        .and_then(|t| t.get("version"))
+    else {
+        return Ok(None);
+    };
+
+    if let Some(attr) = version.get("attr").and_then(|v| v.as_str()) {
+        Ok(Some(VersionSpec::Attr(attr.to_string())))
+    } else if let Some(file) = version.get("file").and_then(|v| v.as_str()) {
+        Ok(Some(VersionSpec::File(file.into())))
+    } else {
+        Ok(None)
+    }
+}
+
+/// A literal version found in Python source code.
+#[derive(Debug, PartialEq, Eq)]
+pub struct PythonVersionLiteral {
+    /// Byte range of the literal in the source.
+    pub span: std::ops::Range<usize>,
+    /// Whether the version is a tuple of integers rather than a string.
+    pub tupled: bool,
+    pub value: String,
+}
+
+impl PythonVersionLiteral {
+    /// Replace the literal in `text` with `new_version`, keeping its style.
+    pub fn replace(&self, text: &str, new_version: &Version) -> String {
+        let old = &text[self.span.clone()];
+        let new = if self.tupled {
+            let mut parts = vec![new_version.major().to_string()];
+            parts.extend(new_version.minor().map(|m| m.to_string()));
+            parts.extend(new_version.micro().map(|m| m.to_string()));
+            format!("({})", parts.join(", "))
+        } else {
+            // Keep string prefixes and the quoting style.
+            let quote_start = old.find(['"', '\'']).unwrap_or(0);
+            let quote_end = quote_start
+                + old[quote_start..]
+                    .find(|c| c != '"' && c != '\'')
+                    .unwrap_or((old.len() - quote_start) / 2);
+            format!(
+                "{}{}{}",
+                &old[..quote_end],
+                new_version.to_string(),
+                &old[quote_start..quote_end]
+            )
+        };
+        format!(
+            "{}{}{}",
+            &text[..self.span.start],
+            new,
+            &text[self.span.end..]
+        )
+    }
+
+    pub fn version(&self) -> Result<Version, Error> {
+        if !lazy_regex::regex_is_match!(r"^[0-9]+(\.[0-9]+){0,2}$", &self.value) {
+            return Err(Error::VersionError(format!(
+                "Unsupported version: {}",
+                self.value
+            )));
+        }
+        Version::from_str(&self.value).map_err(Error::VersionError)
+    }
+}
+
+/// Script that finds a version literal in Python code read from stdin, and
+/// prints its location as JSON. The first argument is the function to call,
+/// the remaining arguments are passed to it.
+const PYTHON_VERSION_FINDER: &str = r#"
+import ast
+import json
+import sys
+
+
+def _literal(node):
+    if isinstance(node, ast.Constant) and isinstance(node.value, str):
+        return False, node.value
+    if isinstance(node, ast.Tuple) and node.elts and all(
+            isinstance(e, ast.Constant) and type(e.value) is int
+            for e in node.elts):
+        return True, '.'.join(str(e.value) for e in node.elts)
+    return None
+
+
+def _result(node):
+    literal = _literal(node)
+    if literal is None:
+        return None
+    return (node.lineno, node.col_offset, node.end_lineno,
+            node.end_col_offset) + literal
+
+
+def find_assignment(source, name):
+    for node in ast.parse(source).body:
+        if isinstance(node, ast.Assign):
+            targets = node.targets
+        elif isinstance(node, ast.AnnAssign) and node.value is not None:
+            targets = [node.target]
+        else:
+            continue
+        if any(isinstance(t, ast.Name) and t.id == name for t in targets):
+            return _result(node.value)
+    return None
+
+
+def find_setup_version(source):
+    for node in ast.walk(ast.parse(source)):
+        if not isinstance(node, ast.Call):
+            continue
+        func = node.func
+        if not ((isinstance(func, ast.Name) and func.id == 'setup') or
+                (isinstance(func, ast.Attribute) and func.attr == 'setup')):
+            continue
+        for kw in node.keywords:
+            if kw.arg != 'version':
+                continue
+            if isinstance(kw.value, ast.Name):
+                return find_assignment(source, kw.value.id)
+            return _result(kw.value)
+    return None
+
+
+if sys.version_info < (3, 8):
+    sys.exit('Python 3.8 or later is required to find versions')
+source = sys.stdin.buffer.read().decode('utf-8')
+json.dump(globals()[sys.argv[1]](source, *sys.argv[2:]), sys.stdout)
+"#;
+
+/// Convert a line number and byte offset as reported by Python's `ast`
+/// module into an offset into `text`.
+fn python_offset(text: &str, lineno: usize, col: usize) -> usize {
+    text.split_inclusive('\n')
+        .take(lineno - 1)
+        .map(|l| l.len())
+        .sum::<usize>()
+        + col
+}
+
+fn call_version_finder(
+    function: &str,
+    text: &str,
+    name: Option<&str>,
+) -> Result<Option<PythonVersionLiteral>, Error> {
+    use std::io::Write;
+    let mut child = std::process::Command::new(DEFAULT_PYTHON)
+        .arg("-c")
+        .arg(PYTHON_VERSION_FINDER)
+        .arg(function)
+        .args(name)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .map_err(|e| Error::Other(format!("Unable to run {}: {}", DEFAULT_PYTHON, e)))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes())?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(Error::Other(format!(
+            "Unable to parse Python code: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    let found: Option<(usize, usize, usize, usize, bool, String)> =
+        serde_json::from_slice(&output.stdout)
+            .map_err(|e| Error::Other(format!("Invalid output from version finder: {}", e)))?;
+    Ok(found.map(
+        |(lineno, col, end_lineno, end_col, tupled, value)| PythonVersionLiteral {
+            span: python_offset(text, lineno, col)..python_offset(text, end_lineno, end_col),
+            tupled,
+            value,
+        },
+    ))
+}
+
+/// Find the literal version passed to `setup()` in a setup.py file.
+///
+/// The version can be passed directly, or through a module-level variable.
+pub fn find_version_literal_in_setup_py(text: &str) -> Result<Option<PythonVersionLiteral>, Error> {
+    call_version_finder("find_setup_version", text, None)
+}
+
+/// Find the literal assigned to a module-level variable, such as `__version__`.
+pub fn find_assignment_in_module(
+    text: &str,
+    name: &str,
+) -> Result<Option<PythonVersionLiteral>, Error> {
+    call_version_finder("find_assignment", text, Some(name))
+}
+
+/// Find the file that defines the module for an `attr:` reference, and the
+/// name of the attribute.
+fn resolve_attr(
+    has_filename: impl Fn(&Path) -> bool,
+    attr: &str,
+) -> Option<(std::path::PathBuf, String)> {
+    let (module, name) = attr.rsplit_once('.')?;
+    let module_path = module.replace('.', "/");
+    [
+        format!("{}.py", module_path),
+        format!("{}/__init__.py", module_path),
+        format!("src/{}.py", module_path),
+        format!("src/{}/__init__.py", module_path),
+    ]
+    .into_iter()
+    .map(std::path::PathBuf::from)
+    .find(|p| has_filename(p))
+    .map(|p| (p, name.to_string()))
+}
+
+fn find_version_in_spec(tree: &dyn Tree, spec: &VersionSpec) -> Result<Option<Version>, Error> {
+    match spec {
+        VersionSpec::Literal(v) => PythonVersionLiteral {
+            span: 0..v.len(),
+            tupled: false,
+            value: v.clone(),
+        }
+        .version()
+        .map(Some),
+        VersionSpec::File(path) => {
+            let text = tree.get_file_text(path)?;
+            Version::from_str(String::from_utf8_lossy(&text).trim())
+                .map(Some)
+                .map_err(Error::VersionError)
+        }
+        VersionSpec::Attr(attr) => {
+            let Some((path, name)) = resolve_attr(|p| tree.has_filename(p), attr) else {
+                log::warn!("Unable to find module for {}", attr);
+                return Ok(None);
+            };
+            let text = String::from_utf8_lossy(&tree.get_file_text(&path)?).into_owned();
+            find_assignment_in_module(&text, &name)?
+                .map(|l| l.version())
+                .transpose()
+        }
+    }
+}
+
+/// Update the version referred to by `spec`, returning the path of the
+/// file that was updated.
+fn update_version_in_spec(
+    tree: &WorkingTree,
+    spec: &VersionSpec,
+    new_version: &Version,
+) -> Result<Option<std::path::PathBuf>, Error> {
+    match spec {
+        VersionSpec::Literal(_) => Ok(None),
+        VersionSpec::File(path) => {
+            tree.put_file_bytes_non_atomic(
+                path,
+                format!("{}\n", new_version.to_string()).as_bytes(),
+            )?;
+            Ok(Some(path.clone()))
+        }
+        VersionSpec::Attr(attr) => {
+            let Some((path, name)) = resolve_attr(|p| tree.has_filename(p), attr) else {
+                return Err(Error::Other(format!("Unable to find module for {}", attr)));
+            };
+            Ok(update_assignment_in_file(tree, &path, &name, new_version)?.then_some(path))
+        }
+    }
+}
+
+/// Update a module-level assignment such as `__version__ = "1.0"` in a file.
+pub fn update_assignment_in_file(
+    tree: &WorkingTree,
+    path: &Path,
+    name: &str,
+    new_version: &Version,
+) -> Result<bool, Error> {
+    let text = String::from_utf8_lossy(&tree.get_file_text(path)?).into_owned();
+    let Some(literal) = find_assignment_in_module(&text, name)? else {
+        return Ok(false);
+    };
+    log::info!("Updating {} in {}", name, path.display());
+    tree.put_file_bytes_non_atomic(path, literal.replace(&text, new_version).as_bytes())?;
+    Ok(true)
+}
+
+/// Find the version of a Python project from setup.cfg, setup.py and the
+/// modules they refer to.
+pub fn find_version_in_setup_files(tree: &dyn Tree) -> Result<Option<Version>, Error> {
+    if let Some(spec) = find_version_spec_in_pyproject_toml(tree)? {
+        if let Some(version) = find_version_in_spec(tree, &spec)? {
+            return Ok(Some(version));
+        }
+    }
+    if tree.has_filename(Path::new("setup.cfg")) {
+        let text =
+            String::from_utf8_lossy(&tree.get_file_text(Path::new("setup.cfg"))?).into_owned();
+        if let Some(spec) = find_version_spec_in_setup_cfg(&text) {
+            if let Some(version) = find_version_in_spec(tree, &spec)? {
+                return Ok(Some(version));
+            }
+        }
+    }
+    if tree.has_filename(Path::new("setup.py")) {
+        let text =
+            String::from_utf8_lossy(&tree.get_file_text(Path::new("setup.py"))?).into_owned();
+        if let Some(literal) = find_version_literal_in_setup_py(&text)? {
+            return literal.version().map(Some);
+        }
+    }
+    Ok(None)
+}
+
+/// Update the version of a Python project.
+///
+/// This updates `[project] version` in pyproject.toml, `[metadata] version`
+/// in setup.cfg and the `version` passed to `setup()` in setup.py. Versions
+/// that are read from a module attribute or a file are updated there. Finally,
+/// `__version__` in the top-level package is updated if it is a literal.
+pub fn update_version_in_python_project(
+    tree: &WorkingTree,
+    new_version: &Version,
+) -> Result<(), Error> {
+    let mut updated = vec![];
+
+    match find_version_backend(tree)? {
+        Some(VersionBackend::Vcs(tool)) => {
+            log::info!("Version is derived from VCS tags by {}", tool);
+            return Ok(());
+        }
+        Some(VersionBackend::Poetry) => {
+            log::info!("Updating version in [tool.poetry]");
+            update_version_in_poetry(tree, new_version)?;
+        }
+        Some(VersionBackend::Module(path)) => {
+            update_assignment_in_file(tree, &path, "__version__", new_version)?;
+            updated.push(path);
+        }
+        Some(VersionBackend::Cargo(path)) => {
+            // The top-level Cargo.toml is updated along with the rest of the
+            // Cargo project.
+            if path != Path::new("Cargo.toml") {
+                log::info!("Updating version in {}", path.display());
+                let text = String::from_utf8_lossy(&tree.get_file_text(&path)?).into_owned();
+                let mut manifest: toml_edit::DocumentMut = text.parse().map_err(|e| {
+                    Error::Other(format!("Invalid TOML in {}: {}", path.display(), e))
+                })?;
+                crate::cargo::update_version_in_toml(&mut manifest, &new_version.to_string())
+                    .map_err(|e| Error::Other(format!("{}: {}", path.display(), e)))?;
+                tree.put_file_bytes_non_atomic(&path, manifest.to_string().as_bytes())?;
+            }
+            return Ok(());
+        }
+        Some(VersionBackend::Static) | Some(VersionBackend::Setuptools) | None => {}
+    }
+
+    if tree.has_filename(Path::new("pyproject.toml")) {
+        update_version_in_pyproject_toml(tree, new_version)?;
+        if let Some(spec) = find_version_spec_in_pyproject_toml(tree)? {
+            if let Some(path) = update_version_in_spec(tree, &spec, new_version)? {
+                updated.push(path);
+            }
+        }
+    }
+
+    if tree.has_filename(Path::new("setup.cfg")) {
+        let text =
+            String::from_utf8_lossy(&tree.get_file_text(Path::new("setup.cfg"))?).into_owned();
+        match find_version_spec_in_setup_cfg(&text) {
+            Some(VersionSpec::Literal(_)) => {
+                let span = find_ini_value(&text, "metadata", "version").unwrap();
+                log::info!("Updating version in setup.cfg");
+                let text = format!(
+                    "{}{}{}",
+                    &text[..span.start],
+                    new_version.to_string(),
+                    &text[span.end..]
+                );
+                tree.put_file_bytes_non_atomic(Path::new("setup.cfg"), text.as_bytes())?;
+            }
+            Some(spec) => {
+                if let Some(path) = update_version_in_spec(tree, &spec, new_version)? {
+                    updated.push(path);
+                }
+            }
+            None => {}
+        }
+    }
+
+    if tree.has_filename(Path::new("setup.py")) {
+        let text =
+            String::from_utf8_lossy(&tree.get_file_text(Path::new("setup.py"))?).into_owned();
+        if let Some(literal) = find_version_literal_in_setup_py(&text)? {
+            log::info!("Updating version in setup.py");
+            tree.put_file_bytes_non_atomic(
+                Path::new("setup.py"),
+                literal.replace(&text, new_version).as_bytes(),
+            )?;
+        }
+    }
+
+    if let Some(name) = find_name_in_python_project(tree) {
+        let attr = format!(
+            "{}.__version__",
+            name.replace(['-', '.'], "_").to_lowercase()
+        );
+        if let Some((path, name)) = resolve_attr(|p| tree.has_filename(p), &attr) {
+            if !updated.contains(&path) {
+                update_assignment_in_file(tree, &path, &name, new_version)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Find the distribution name of a Python project.
+pub fn find_name_in_python_project(tree: &dyn Tree) -> Option<String> {
+    if let Some(name) = find_name_in_pyproject_toml(tree) {
+        return Some(name);
+    }
+    let text = tree.get_file_text(Path::new("setup.cfg")).ok()?;
+    let text = String::from_utf8_lossy(&text);
+    let span = find_ini_value(&text, "metadata", "name")?;
+    Some(text[span].to_string())
+}
+
+/// The mechanism a Python project uses to declare its version.
+#[derive(Debug, PartialEq, Eq)]
+pub enum VersionBackend {
+    /// `[project] version` in pyproject.toml.
+    Static,
+    /// The version is derived from VCS tags by the named tool, so there is
+    /// nothing to update in the tree.
+    Vcs(&'static str),
+    /// `[tool.poetry] version` in pyproject.toml.
+    Poetry,
+    /// `__version__` in a module, as used by flit and pdm.
+    Module(std::path::PathBuf),
+    /// setuptools metadata in setup.cfg, setup.py or `[tool.setuptools.dynamic]`.
+    Setuptools,
+    /// The package version in a Cargo manifest, as used by maturin.
+    Cargo(std::path::PathBuf),
+}
+
+fn read_pyproject_toml(tree: &dyn Tree) -> Result<Option<toml_edit::DocumentMut>, Error> {
+    let content = match tree.get_file_text(Path::new("pyproject.toml")) {
+        Ok(v) => v,
+        Err(BrzError::NoSuchFile(_)) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+
+    Ok(Some(
+        String::from_utf8(content)
+            .map_err(|e| Error::Other(format!("Invalid UTF-8 in pyproject.toml: {}", e)))?
+            .parse()
+            .map_err(|e| Error::Other(format!("Invalid TOML in pyproject.toml: {}", e)))?,
+    ))
+}
+
+/// Find the file that defines `__version__` for a module name.
+fn find_module_file(
+    has_filename: impl Fn(&Path) -> bool,
+    module: &str,
+) -> Option<std::path::PathBuf> {
+    resolve_attr(has_filename, &format!("{}.__version__", module)).map(|(p, _)| p)
+}
+
+/// Determine the version backend from a parsed pyproject.toml.
+fn version_backend_from_pyproject(
+    has_filename: impl Fn(&Path) -> bool,
+    pyproject: &toml_edit::DocumentMut,
+) -> Option<VersionBackend> {
+    let tool = pyproject.get("tool");
+    let project = pyproject.get("project");
+    let dynamic_version = project
+        .and_then(|p| p.get("dynamic"))
+        .and_then(|d| d.as_array())
+        .is_some_and(|d| d.iter().any(|v| v.as_str() == Some("version")));
+    let build_backend = pyproject
+        .get("build-system")
+        .and_then(|b| b.get("build-backend"))
+        .and_then(|b| b.as_str())
+        .unwrap_or_default();
+    let requires_scm = pyproject
+        .get("build-system")
+        .and_then(|b| b.get("requires"))
+        .and_then(|r| r.as_array())
+        .is_some_and(|r| {
+            r.iter().filter_map(|v| v.as_str()).any(|v| {
+                let v = v.to_lowercase().replace('_', "-");
+                v.starts_with("setuptools-scm") || v.starts_with("hatch-vcs")
+            })
+        });
+
+    if tool
+        .and_then(|t| t.get("hatch"))
+        .and_then(|t| t.get("version"))
+        .and_then(|t| t.get("source"))
+        .and_then(|s| s.as_str())
+        == Some("vcs")
+    {
+        return Some(VersionBackend::Vcs("hatch-vcs"));
+    }
+
+    if tool.and_then(|t| t.get("setuptools_scm")).is_some()
+        || (dynamic_version && requires_scm && build_backend.starts_with("setuptools"))
+    {
+        return Some(VersionBackend::Vcs("setuptools-scm"));
+    }
+
+    if let Some(pdm_version) = tool
+        .and_then(|t| t.get("pdm"))
+        .and_then(|t| t.get("version"))
+    {
+        match pdm_version.get("source").and_then(|s| s.as_str()) {
+            Some("scm") => return Some(VersionBackend::Vcs("pdm")),
+            Some("file") => {
+                if let Some(path) = pdm_version.get("path").and_then(|p| p.as_str()) {
+                    return Some(VersionBackend::Module(path.into()));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if build_backend == "maturin" && project.and_then(|p| p.get("version")).is_none() {
+        let manifest = tool
+            .and_then(|t| t.get("maturin"))
+            .and_then(|t| t.get("manifest-path"))
+            .and_then(|p| p.as_str())
+            .unwrap_or("Cargo.toml");
+        return Some(VersionBackend::Cargo(manifest.into()));
+    }
+
+    if tool
+        .and_then(|t| t.get("poetry-dynamic-versioning"))
+        .and_then(|t| t.get("enable"))
+        .and_then(|e| e.as_bool())
+        == Some(true)
+    {
+        return Some(VersionBackend::Vcs("poetry-dynamic-versioning"));
+    }
+
+    if tool
+        .and_then(|t| t.get("poetry"))
+        .and_then(|t| t.get("version"))
+        .is_some()
+        && project.and_then(|p| p.get("version")).is_none()
+    {
+        return Some(VersionBackend::Poetry);
+    }
+
+    if project.and_then(|p| p.get("version")).is_some() {
+        return Some(VersionBackend::Static);
+    }
+
+    if dynamic_version && build_backend.starts_with("flit") {
+        let module = tool
+            .and_then(|t| t.get("flit"))
+            .and_then(|t| t.get("module"))
+            .and_then(|t| t.get("name"))
+            .and_then(|n| n.as_str())
+            .map(|n| n.to_string())
+            .or_else(|| {
+                project
+                    .and_then(|p| p.get("name"))
+                    .and_then(|n| n.as_str())
+                    .map(|n| n.replace('-', "_"))
+            })?;
+        return find_module_file(has_filename, &module).map(VersionBackend::Module);
+    }
+
+    Some(VersionBackend::Setuptools)
+}
+
+/// Determine how a Python project declares its version.
+pub fn find_version_backend(tree: &dyn Tree) -> Result<Option<VersionBackend>, Error> {
+    if let Some(pyproject) = read_pyproject_toml(tree)? {
+        return Ok(version_backend_from_pyproject(
+            |p| tree.has_filename(p),
+            &pyproject,
+        ));
+    }
+    if tree.has_filename(Path::new("setup.py")) {
+        let text = tree.get_file_text(Path::new("setup.py"))?;
+        if String::from_utf8_lossy(&text).contains("use_scm_version") {
+            return Ok(Some(VersionBackend::Vcs("setuptools-scm")));
+        }
+    }
+    if tree.has_filename(Path::new("setup.py")) || tree.has_filename(Path::new("setup.cfg")) {
+        return Ok(Some(VersionBackend::Setuptools));
+    }
+    Ok(None)
+}
+
+fn find_version_in_poetry(pyproject: &toml_edit::DocumentMut) -> Result<Option<Version>, Error> {
+    pyproject
+        .get("tool")
+        .and_then(|t| t.get("poetry"))
+        .and_then(|t| t.get("version"))
+        .and_then(|v| v.as_str())
+        .map(|v| Version::from_str(v).map_err(Error::VersionError))
+        .transpose()
+}
+
+/// Find the current version of a Python project.
+///
+/// Returns `None` if the version is derived from VCS tags.
+pub fn find_version_in_python_project(tree: &dyn Tree) -> Result<Option<Version>, Error> {
+    match find_version_backend(tree)? {
+        None | Some(VersionBackend::Vcs(_)) => Ok(None),
+        Some(VersionBackend::Static) => find_version_in_pyproject_toml(tree),
+        Some(VersionBackend::Poetry) => match read_pyproject_toml(tree)? {
+            Some(pyproject) => find_version_in_poetry(&pyproject),
+            None => Ok(None),
+        },
+        Some(VersionBackend::Module(path)) => {
+            let text = String::from_utf8_lossy(&tree.get_file_text(&path)?).into_owned();
+            find_assignment_in_module(&text, "__version__")?
+                .map(|l| l.version())
+                .transpose()
+        }
+        Some(VersionBackend::Setuptools) => find_version_in_setup_files(tree),
+        Some(VersionBackend::Cargo(path)) => {
+            let text = String::from_utf8_lossy(&tree.get_file_text(&path)?).into_owned();
+            crate::cargo::find_version_in_toml(&text)
+                .map(Some)
+                .map_err(|e| Error::Other(format!("{}: {}", path.display(), e)))
+        }
+    }
+}
+
+fn update_version_in_poetry(tree: &WorkingTree, new_version: &Version) -> Result<bool, Error> {
+    let Some(mut pyproject) = read_pyproject_toml(tree)? else {
+        return Ok(false);
+    };
+    let Some(version) = pyproject
+        .get_mut("tool")
+        .and_then(|t| t.get_mut("poetry"))
+        .and_then(|t| t.get_mut("version"))
+    else {
+        return Ok(false);
+    };
+    *version = toml_edit::value(new_version.to_string());
+    tree.put_file_bytes_non_atomic(
+        Path::new("pyproject.toml"),
+        pyproject.to_string().as_bytes(),
+    )?;
+    Ok(true)
+}
+
 pub fn read_project_urls_from_pyproject_toml(
     path: &std::path::Path,
 ) -> Result<Vec<(url::Url, Option<String>)>, Error> {
@@ -300,141 +1130,549 @@ pub fn read_project_urls_from_setup_cfg(
     })
 }
 
+/// The interpreter used to build Python distributions, unless configured
+/// otherwise.
+pub const DEFAULT_PYTHON: &str = "python3";
+
+/// A PEP 517 build of a Python project failed.
 #[derive(Debug)]
-pub struct UploadCommandFailed {
+pub struct BuildFailed {
     pub command: Vec<String>,
-    pub retcode: Option<i32>,
+    pub status: Option<std::process::ExitStatus>,
+    /// Combined stdout and stderr of the build.
+    pub output: String,
 }
 
-impl std::fmt::Display for UploadCommandFailed {
+impl std::fmt::Display for BuildFailed {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "`{}` failed", self.command.join(" "))
+        write!(f, "`{}` failed", self.command.join(" "))?;
+        if let Some(status) = self.status {
+            write!(f, " ({})", status)?;
+        }
+        Ok(())
     }
 }
 
-impl std::error::Error for UploadCommandFailed {}
+impl std::error::Error for BuildFailed {}
 
-pub fn upload_python_artifacts(
+/// Run a build command that writes its results to an output directory,
+/// and copy the results into `dist` in the tree.
+///
+/// `command` is called with the path of a temporary output directory and
+/// returns the command line to run.
+fn run_build(
     local_tree: &WorkingTree,
-    pypi_paths: &[&std::path::Path],
-) -> Result<(), UploadCommandFailed> {
-    let mut command = vec!["twine", "upload", "--non-interactive"];
-    command.extend(pypi_paths.iter().map(|v| v.to_str().unwrap()));
+    command: impl FnOnce(&Path) -> Vec<String>,
+) -> Result<Vec<std::path::PathBuf>, BuildFailed> {
+    let source_dir = local_tree.abspath(Path::new(".")).unwrap();
+    let dist_dir = local_tree.abspath(Path::new("dist")).unwrap();
+
+    let out_dir = tempfile::tempdir().map_err(|e| BuildFailed {
+        command: vec![],
+        status: None,
+        output: format!("Unable to create temporary directory: {}", e),
+    })?;
 
-    let abs_path = local_tree.abspath(Path::new(".")).unwrap();
+    let command = command(out_dir.path());
 
-    let output = Command::new("twine")
+    log::debug!("Running {:?}", command);
+    let output = std::process::Command::new(&command[0])
         .args(&command[1..])
-        .current_dir(&abs_path)
-        .status();
-
-    match output {
-        Ok(status) => {
-            if status.success() {
-                Ok(())
-            } else {
-                Err(UploadCommandFailed {
-                    command: command.iter().map(|v| v.to_string()).collect(),
-                    retcode: status.code(),
-                })
+        .current_dir(&source_dir)
+        .stdin(std::process::Stdio::null())
+        .output()
+        .map_err(|e| BuildFailed {
+            command: command.clone(),
+            status: None,
+            output: e.to_string(),
+        })?;
+
+    let log = format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    if !output.status.success() {
+        return Err(BuildFailed {
+            command,
+            status: Some(output.status),
+            output: log,
+        });
+    }
+    log::debug!("{}", log);
+
+    let fail = |e: std::io::Error| BuildFailed {
+        command: command.clone(),
+        status: Some(output.status),
+        output: e.to_string(),
+    };
+
+    std::fs::create_dir_all(&dist_dir).map_err(fail)?;
+
+    let mut entries = std::fs::read_dir(out_dir.path())
+        .map_err(fail)?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(fail)?;
+    entries.sort_by_key(|e| e.file_name());
+    let mut paths = vec![];
+    for entry in entries {
+        let target = dist_dir.join(entry.file_name());
+        std::fs::copy(entry.path(), &target).map_err(fail)?;
+        paths.push(target);
+    }
+    Ok(paths)
+}
+
+/// Build the sdist and wheel for a Python project.
+///
+/// The build runs `python -m build` in a subprocess, which creates an
+/// isolated environment with the build dependencies declared by the project.
+/// The resulting files are moved into the `dist` directory of the tree.
+///
+/// maturin projects are built with [`create_maturin_sdist`] and
+/// [`build_binary_wheels`] instead.
+///
+/// Wheels that are not pure Python are only usable on the platform they were
+/// built on, so they are not returned; see [`build_binary_wheels`].
+pub fn create_python_artifacts(
+    local_tree: &WorkingTree,
+    python: Option<&str>,
+) -> Result<Vec<std::path::PathBuf>, BuildFailed> {
+    let python = python.unwrap_or(DEFAULT_PYTHON);
+    let source_dir = local_tree.abspath(Path::new(".")).unwrap();
+
+    let paths = run_build(local_tree, |out_dir| {
+        vec![
+            python.to_string(),
+            "-m".to_string(),
+            "build".to_string(),
+            "--outdir".to_string(),
+            out_dir.to_string_lossy().into_owned(),
+            source_dir.to_string_lossy().into_owned(),
+        ]
+    })?;
+
+    Ok(paths
+        .into_iter()
+        .filter(|path| {
+            let filename = path.file_name().unwrap().to_string_lossy();
+            match crate::pypi::WheelFilename::parse(&filename) {
+                Some(wheel) if !wheel.is_pure() => {
+                    log::warn!(
+                        "{} is not a pure python wheel; not uploading binary wheels",
+                        filename
+                    );
+                    false
+                }
+                _ => true,
             }
+        })
+        .collect())
+}
+
+/// Whether a Python project is built with maturin.
+pub fn uses_maturin(tree: &dyn Tree) -> Result<bool, Error> {
+    Ok(read_pyproject_toml(tree)?
+        .and_then(|p| {
+            p.get("build-system")
+                .and_then(|b| b.get("build-backend"))
+                .and_then(|b| b.as_str())
+                .map(|b| b == "maturin")
+        })
+        .unwrap_or(false))
+}
+
+/// Build an sdist of a maturin project, including the Rust sources.
+pub fn create_maturin_sdist(
+    local_tree: &WorkingTree,
+) -> Result<Vec<std::path::PathBuf>, BuildFailed> {
+    run_build(local_tree, |out_dir| {
+        vec![
+            "maturin".to_string(),
+            "sdist".to_string(),
+            "--out".to_string(),
+            out_dir.to_string_lossy().into_owned(),
+        ]
+    })
+}
+
+/// Build binary wheels that can be uploaded to PyPI.
+///
+/// cibuildwheel builds manylinux and musllinux wheels in containers, so it
+/// needs docker or podman. maturin builds a wheel for the local platform.
+pub fn build_binary_wheels(
+    local_tree: &WorkingTree,
+    builder: WheelBuilder,
+    python: Option<&str>,
+) -> Result<Vec<std::path::PathBuf>, BuildFailed> {
+    let python = python.unwrap_or(DEFAULT_PYTHON);
+    let paths = run_build(local_tree, |out_dir| {
+        let out_dir = out_dir.to_string_lossy().into_owned();
+        match builder {
+            WheelBuilder::Cibuildwheel => vec![
+                python.to_string(),
+                "-m".to_string(),
+                "cibuildwheel".to_string(),
+                "--platform".to_string(),
+                "linux".to_string(),
+                "--output-dir".to_string(),
+                out_dir,
+            ],
+            WheelBuilder::Maturin => vec![
+                "maturin".to_string(),
+                "build".to_string(),
+                "--release".to_string(),
+                "--interpreter".to_string(),
+                python.to_string(),
+                "--out".to_string(),
+                out_dir,
+            ],
         }
-        Err(_) => Err(UploadCommandFailed {
-            command: command.iter().map(|v| v.to_string()).collect(),
-            retcode: None,
-        }),
+    })?;
+
+    Ok(paths
+        .into_iter()
+        .filter(|path| {
+            let filename = path.file_name().unwrap().to_string_lossy();
+            match crate::pypi::WheelFilename::parse(&filename) {
+                // PyPI only accepts binary wheels for portable platforms.
+                Some(wheel) if wheel.platform.starts_with("linux_") => {
+                    log::warn!("{} is not a portable wheel; not uploading", filename);
+                    false
+                }
+                Some(_) => true,
+                None => false,
+            }
+        })
+        .collect())
+}
+
+/// Files that are commonly versioned but not needed in an sdist. These
+/// match the defaults of check-manifest.
+const DEFAULT_SDIST_IGNORE: &[&str] = &[
+    "PKG-INFO",
+    "*.egg-info/*",
+    ".hgtags",
+    ".hgsigs",
+    ".hgignore",
+    ".gitignore",
+    ".bzrignore",
+    ".gitattributes",
+    ".github/*",
+    ".travis.yml",
+    "Jenkinsfile",
+    "*.mo",
+    "disperse.toml",
+];
+
+/// List the files in an sdist, relative to its top-level directory.
+pub fn sdist_file_list(path: &Path) -> Result<Vec<std::path::PathBuf>, Error> {
+    let f = std::fs::File::open(path)?;
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(f));
+    let mut ret = vec![];
+    for entry in archive.entries()? {
+        let entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry.path()?;
+        let mut components = path.components();
+        components.next();
+        ret.push(components.as_path().to_path_buf());
     }
+    Ok(ret)
 }
 
-pub fn create_setup_py_artifacts(
-    local_tree: &WorkingTree,
-) -> pyo3::PyResult<Vec<std::path::PathBuf>> {
-    pyo3::Python::with_gil(|py| {
-        // Initialize an empty vector to store pypi_paths
-        let mut pypi_paths: Vec<std::path::PathBuf> = Vec::new();
+/// Find versioned files that are missing from an sdist.
+///
+/// Files matching any of the `ignore` globs, or the default ignore list, are
+/// not reported.
+pub fn missing_from_sdist(
+    versioned: &[std::path::PathBuf],
+    sdist_files: &[std::path::PathBuf],
+    ignore: &[String],
+) -> Result<Vec<std::path::PathBuf>, Error> {
+    let patterns = DEFAULT_SDIST_IGNORE
+        .iter()
+        .copied()
+        .chain(ignore.iter().map(|s| s.as_str()))
+        .map(|p| {
+            glob::Pattern::new(p)
+                .map_err(|e| Error::Other(format!("Invalid ignore pattern {}: {}", p, e)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(versioned
+        .iter()
+        .filter(|p| !sdist_files.contains(p))
+        .filter(|p| !patterns.iter().any(|pat| pat.matches_path(p)))
+        .cloned()
+        .collect())
+}
 
-        // Import required Python modules
-        let os = py.import_bound("os")?;
-        let run_setup = py.import_bound("distutils.core")?.getattr("run_setup")?;
-        let _setuptools = py.import_bound("setuptools")?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_version_spec_in_setup_cfg() {
+        assert_eq!(
+            find_version_spec_in_setup_cfg("[metadata]\nname = foo\nversion = 1.2.3\n"),
+            Some(VersionSpec::Literal("1.2.3".to_string()))
+        );
+        assert_eq!(
+            find_version_spec_in_setup_cfg("[metadata]\nversion = attr: foo.__version__\n"),
+            Some(VersionSpec::Attr("foo.__version__".to_string()))
+        );
+        assert_eq!(
+            find_version_spec_in_setup_cfg("[metadata]\nversion = file: VERSION\n"),
+            Some(VersionSpec::File("VERSION".into()))
+        );
+        assert_eq!(
+            find_version_spec_in_setup_cfg("[options]\nversion = 1.2.3\n"),
+            None
+        );
+    }
 
-        // Save the original directory
-        let orig_dir = os.call_method0("getcwd")?;
+    #[test]
+    fn test_repository_url_from_project_urls() {
+        let urls = serde_json::json!({
+            "Homepage": "https://www.dulwich.io/",
+            "Repository": "https://github.com/jelmer/dulwich",
+        });
+        assert_eq!(
+            repository_url_from_project_urls(urls.as_object().unwrap(), None),
+            Some("https://github.com/jelmer/dulwich".parse().unwrap())
+        );
+
+        let urls = serde_json::json!({
+            "Bug Tracker": "https://github.com/jelmer/disperse/issues",
+            "GitHub": "https://github.com/jelmer/disperse",
+        });
+        assert_eq!(
+            repository_url_from_project_urls(urls.as_object().unwrap(), None),
+            Some("https://github.com/jelmer/disperse".parse().unwrap())
+        );
+
+        let urls = serde_json::json!({
+            "Source": "https://git.example.com/jelmer/disperse",
+        });
+        assert_eq!(
+            repository_url_from_project_urls(urls.as_object().unwrap(), None),
+            None
+        );
+        let hosts = HashMap::from([("git.example.com".to_string(), GitHubHostConfig::default())]);
+        assert_eq!(
+            repository_url_from_project_urls(urls.as_object().unwrap(), Some(&hosts)),
+            Some("https://git.example.com/jelmer/disperse".parse().unwrap())
+        );
+
+        let urls = serde_json::json!({"Homepage": "UNKNOWN"});
+        assert_eq!(
+            repository_url_from_project_urls(urls.as_object().unwrap(), None),
+            None
+        );
+    }
 
-        // Change to the setup.py directory
-        let setup_dir = local_tree.abspath(Path::new(".")).unwrap();
-        os.call_method1("chdir", (setup_dir.clone(),))?;
+    #[test]
+    fn test_missing_from_sdist() {
+        let versioned = [
+            "setup.py",
+            "foo/__init__.py",
+            "foo/data.json",
+            ".github/workflows/ci.yml",
+            "docs/index.rst",
+        ]
+        .iter()
+        .map(std::path::PathBuf::from)
+        .collect::<Vec<_>>();
+        let sdist = ["PKG-INFO", "setup.py", "foo/__init__.py"]
+            .iter()
+            .map(std::path::PathBuf::from)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            missing_from_sdist(&versioned, &sdist, &[]).unwrap(),
+            vec![
+                std::path::PathBuf::from("foo/data.json"),
+                std::path::PathBuf::from("docs/index.rst")
+            ]
+        );
+        assert_eq!(
+            missing_from_sdist(&versioned, &sdist, &["docs/*".to_string()]).unwrap(),
+            vec![std::path::PathBuf::from("foo/data.json")]
+        );
+        assert!(missing_from_sdist(&versioned, &sdist, &["[".to_string()]).is_err());
+    }
 
-        let result = {
-            // Attempt to run setup.py
-            let run_setup_result =
-                run_setup.call1((local_tree.abspath(Path::new("setup.py")).unwrap(), "config"))?;
-            // Change back to the original directory
-            os.call_method1("chdir", (orig_dir,))?;
+    #[test]
+    fn test_find_ini_value() {
+        let text = "[metadata]\nname = foo\nversion= 1.2.3  \n[options]\nversion = 2\n";
+        let span = find_ini_value(text, "metadata", "version").unwrap();
+        assert_eq!(&text[span], "1.2.3");
+        let span = find_ini_value(text, "options", "version").unwrap();
+        assert_eq!(&text[span], "2");
+    }
 
-            run_setup_result
-        };
+    #[test]
+    fn test_setup_py_literal() {
+        let text =
+            "from setuptools import setup\n\nsetup(\n    name='foo',\n    version='1.2.3',\n)\n";
+        let literal = find_version_literal_in_setup_py(text).unwrap().unwrap();
+        assert_eq!(literal.value, "1.2.3");
+        assert_eq!(literal.version().unwrap(), "1.2.3".parse().unwrap());
+        assert_eq!(
+            literal.replace(text, &"1.3.0".parse().unwrap()),
+            "from setuptools import setup\n\nsetup(\n    name='foo',\n    version='1.3.0',\n)\n"
+        );
+    }
 
-        // Check for C libraries and extension modules
-        let is_pure = !result
-            .call_method0("has_c_libraries")?
-            .extract::<Option<bool>>()?
-            .unwrap_or(false)
-            && !result
-                .call_method0("has_ext_modules")?
-                .extract::<Option<bool>>()?
-                .unwrap_or(false);
-
-        let builder = py
-            .import_bound("build")?
-            .call_method1("ProjectBuilder", (setup_dir,))?;
-
-        if is_pure {
-            let wheels = builder.call_method1(
-                "build",
-                ("wheel", local_tree.abspath(Path::new("dist")).unwrap()),
-            )?;
-            pypi_paths.push(wheels.extract::<std::path::PathBuf>()?);
-        } else {
-            log::warn!("python module is not pure; not uploading binary wheels");
-        }
+    #[test]
+    fn test_setup_py_variable() {
+        let text = "import setuptools\nversion_string = \"0.2.0\"\nsetuptools.setup(name='foo', version=version_string)\n";
+        let literal = find_version_literal_in_setup_py(text).unwrap().unwrap();
+        assert_eq!(
+            literal.replace(text, &"0.3.0".parse().unwrap()),
+            "import setuptools\nversion_string = \"0.3.0\"\nsetuptools.setup(name='foo', version=version_string)\n"
+        );
+    }
 
-        let sdist_path = builder.call_method1(
-            "build",
-            ("sdist", local_tree.abspath(Path::new("dist")).unwrap()),
-        )?;
-        pypi_paths.push(sdist_path.extract::<std::path::PathBuf>()?);
+    #[test]
+    fn test_setup_py_computed() {
+        let text = "from setuptools import setup\nsetup(version=get_version())\n";
+        assert_eq!(find_version_literal_in_setup_py(text).unwrap(), None);
+    }
 
-        Ok(pypi_paths)
-    })
-}
+    #[test]
+    fn test_module_tupled_version() {
+        let text = "\"\"\"Docstring.\"\"\"\n\n__version__ = (0, 22, 1)\n";
+        let literal = find_assignment_in_module(text, "__version__")
+            .unwrap()
+            .unwrap();
+        assert!(literal.tupled);
+        assert_eq!(literal.version().unwrap(), "0.22.1".parse().unwrap());
+        assert_eq!(
+            literal.replace(text, &"0.23.0".parse().unwrap()),
+            "\"\"\"Docstring.\"\"\"\n\n__version__ = (0, 23, 0)\n"
+        );
+    }
 
-pub fn create_python_artifacts(
-    local_tree: &WorkingTree,
-) -> pyo3::PyResult<Vec<std::path::PathBuf>> {
-    pyo3::Python::with_gil(|py| {
-        let mut pypi_paths = Vec::new();
+    fn backend(pyproject: &str, files: &[&str]) -> Option<VersionBackend> {
+        version_backend_from_pyproject(
+            |p| files.iter().any(|f| Path::new(f) == p),
+            &pyproject.parse().unwrap(),
+        )
+    }
 
-        let project_builder = py.import_bound("build")?.call_method1(
-            "ProjectBuilder",
-            (local_tree.abspath(Path::new(".")).unwrap(),),
-        )?;
+    #[test]
+    fn test_version_backend_static() {
+        assert_eq!(
+            backend("[project]\nname = \"foo\"\nversion = \"1.0\"\n", &[]),
+            Some(VersionBackend::Static)
+        );
+    }
 
-        // Wrap Python exception handling using PyResult
-        let wheels = project_builder.call_method1(
-            "build",
-            ("wheel", local_tree.abspath(Path::new("dist")).unwrap()),
-        )?;
+    #[test]
+    fn test_version_backend_vcs() {
+        assert_eq!(
+            backend(
+                "[project]\ndynamic = [\"version\"]\n[tool.hatch.version]\nsource = \"vcs\"\n",
+                &[]
+            ),
+            Some(VersionBackend::Vcs("hatch-vcs"))
+        );
+        assert_eq!(
+            backend(
+                "[build-system]\nrequires = [\"setuptools>=64\", \"setuptools_scm>=8\"]\nbuild-backend = \"setuptools.build_meta\"\n[project]\ndynamic = [\"version\"]\n",
+                &[]
+            ),
+            Some(VersionBackend::Vcs("setuptools-scm"))
+        );
+        assert_eq!(
+            backend("[tool.setuptools_scm]\n", &[]),
+            Some(VersionBackend::Vcs("setuptools-scm"))
+        );
+        assert_eq!(
+            backend("[tool.pdm.version]\nsource = \"scm\"\n", &[]),
+            Some(VersionBackend::Vcs("pdm"))
+        );
+        assert_eq!(
+            backend(
+                "[tool.poetry]\nversion = \"0.0.0\"\n[tool.poetry-dynamic-versioning]\nenable = true\n",
+                &[]
+            ),
+            Some(VersionBackend::Vcs("poetry-dynamic-versioning"))
+        );
+    }
+
+    #[test]
+    fn test_version_backend_maturin() {
+        assert_eq!(
+            backend(
+                "[build-system]\nbuild-backend = \"maturin\"\n[project]\nname = \"foo\"\ndynamic = [\"version\"]\n",
+                &[]
+            ),
+            Some(VersionBackend::Cargo("Cargo.toml".into()))
+        );
+        assert_eq!(
+            backend(
+                "[build-system]\nbuild-backend = \"maturin\"\n[project]\nname = \"foo\"\n[tool.maturin]\nmanifest-path = \"rust/Cargo.toml\"\n",
+                &[]
+            ),
+            Some(VersionBackend::Cargo("rust/Cargo.toml".into()))
+        );
+        assert_eq!(
+            backend(
+                "[build-system]\nbuild-backend = \"maturin\"\n[project]\nname = \"foo\"\nversion = \"1.0\"\n",
+                &[]
+            ),
+            Some(VersionBackend::Static)
+        );
+    }
 
-        pypi_paths.push(std::path::PathBuf::from(wheels.extract::<String>()?));
+    #[test]
+    fn test_version_backend_poetry() {
+        assert_eq!(
+            backend("[tool.poetry]\nname = \"foo\"\nversion = \"1.0\"\n", &[]),
+            Some(VersionBackend::Poetry)
+        );
+    }
 
-        let sdist_path = project_builder.call_method1(
-            "build",
-            ("source", local_tree.abspath(Path::new("dist")).unwrap()),
-        )?;
+    #[test]
+    fn test_version_backend_module() {
+        assert_eq!(
+            backend(
+                "[build-system]\nbuild-backend = \"flit_core.buildapi\"\n[project]\nname = \"foo-bar\"\ndynamic = [\"version\", \"description\"]\n",
+                &["src/foo_bar/__init__.py"]
+            ),
+            Some(VersionBackend::Module("src/foo_bar/__init__.py".into()))
+        );
+        assert_eq!(
+            backend(
+                "[tool.pdm.version]\nsource = \"file\"\npath = \"foo/__init__.py\"\n",
+                &[]
+            ),
+            Some(VersionBackend::Module("foo/__init__.py".into()))
+        );
+    }
 
-        pypi_paths.push(std::path::PathBuf::from(sdist_path.extract::<String>()?));
+    #[test]
+    fn test_version_backend_setuptools() {
+        assert_eq!(
+            backend(
+                "[project]\nname = \"foo\"\ndynamic = [\"version\"]\n[tool.setuptools.dynamic]\nversion = {attr = \"foo.__version__\"}\n",
+                &[]
+            ),
+            Some(VersionBackend::Setuptools)
+        );
+    }
 
-        Ok(pypi_paths)
-    })
+    #[test]
+    fn test_unsupported_version() {
+        let text = "__version__ = '1.0.dev0'\n";
+        let literal = find_assignment_in_module(text, "__version__")
+            .unwrap()
+            .unwrap();
+        assert!(literal.version().is_err());
+    }
 }